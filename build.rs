@@ -0,0 +1,21 @@
+//! Detects compiler support for `#[diagnostic]` attributes, stable since
+//! Rust 1.78, which give `#[enclave]` misuse a readable error.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(diagnostic_namespace)");
+
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let minor = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .and_then(|version| version.split('.').nth(1)?.parse::<u32>().ok());
+    if minor.map_or(false, |minor| minor >= 78) {
+        println!("cargo:rustc-cfg=diagnostic_namespace");
+    }
+}
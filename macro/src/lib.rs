@@ -1,78 +1,433 @@
-#![feature(proc_macro_diagnostic)]
-
 //! Macro crate for `binary_enclave`.
 
+#[cfg(doctest)]
+mod malformed;
+mod max_size;
+
 use proc_macro::TokenStream;
-use proc_macro2::{Ident, Span};
-use quote::quote;
-use syn::spanned::Spanned;
-use syn::{parse_macro_input, GenericArgument, ItemStatic, PathArguments, Type};
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use proc_macro_crate::{crate_name, FoundCrate};
+use quote::{format_ident, quote, quote_spanned, ToTokens};
+use syn::{
+    parse_macro_input, parse_quote, AttributeArgs, DeriveInput, Expr, GenericArgument, ItemStatic,
+    Lit, Meta, NestedMeta, PathArguments, Type,
+};
+
+/// Longest section name Mach-O can hold, its 16 bytes include the `__`.
+const MACHO_SECTION_MAX: usize = 14;
+
+/// Longest section name PE images keep, their 8 bytes include the `.`.
+const PE_SECTION_MAX: usize = 7;
 
 /// setup required linker options and trait impls
 ///
 /// this puts in to place whats required for our enclave. Without
 /// it, it will not be locatable for writing to after compilation.
-/// Some systems may limit the length of section names. It is
-/// recommended to keep the section name short and simple.
+/// Section names may only use ASCII letters, digits and `_`, and are
+/// limited to 14 bytes as Mach-O only keeps 16 bytes of a section name,
+/// including the leading `__`. PE (Windows) images only keep 8 bytes,
+/// including the leading `.`, so longer names fail to build for Windows.
+///
+/// The payload checksum can be chosen with `checksum`, one of `crc32c`
+/// (the default), `xxhash64` or `sha256`. Giving an Ed25519 `public_key`
+/// as hex requires the payload to be signed by the matching private key,
+/// and `binary_enclave` to be built with its `signing` feature.
+/// `codec` picks the serialization format, one of `bincode` (the default),
+/// `postcard`, `cbor`, `msgpack` or `json`, enabling its cargo feature.
+/// `version` numbers the payload type for migrations, starting at `0`.
+/// The payload type's layout is fingerprinted on first use, types serde
+/// cannot trace, such as those with untagged enums, are not checked.
+/// `backups` keeps that many previous payloads for `Enclave::rollback`.
+/// `slots = 2` splits the enclave into A/B slots for power-loss safety.
+/// `init` bakes a file, already serialized with the enclave's codec, in as
+/// the initial payload. Relative paths are taken from the crate root. Files
+/// named `.json`, `.cbor`, `.msgpack` or `.mpk` must match the codec, and
+/// seeded enclaves cannot have a `public_key` as the seed is unsigned.
+///
+/// The static may name its type through any path, `binary_enclave::Enclave`
+/// or a re-export, or through a type alias. Its settings are attached to a
+/// marker type generated for the static rather than to the payload type,
+/// so several enclaves may share a payload type. The marker fills in the
+/// last parameter of `Enclave`, which `Enclave::new()` infers as usual.
+/// Generated code refers to `binary_enclave` under whatever name the
+/// calling crate depends on it by, `crate = "..."` gives the path
+/// explicitly when it is only reachable through another crate.
+///
+/// For payload types implementing `MaxSize`, `max_size` fails the build if
+/// the enclave cannot hold their largest payload, and declaring the static
+/// as `Enclave<T, _>` computes the size instead. Both need bincode.
 ///
 /// ```
-/// #[enclave(binary_section_name)]
-/// pub static OUR_STATIC: Enclave<ConfStruct, 128> = Enclave::new()
+/// # use binary_enclave::{enclave, Enclave, MaxSize};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Default, Serialize, Deserialize)]
+/// # pub struct ConfStruct { name: String }
+/// # #[derive(Default, Serialize, Deserialize, MaxSize)]
+/// # pub struct BoundedStruct { port: u16 }
+/// #[enclave(appconf)]
+/// pub static OUR_STATIC: Enclave<ConfStruct, 128> = Enclave::new();
+///
+/// #[enclave(other, checksum = "sha256")]
+/// pub static OTHER_STATIC: Enclave<ConfStruct, 128> = Enclave::new();
+///
+/// #[enclave(sized)]
+/// pub static SIZED_STATIC: Enclave<BoundedStruct, _> = Enclave::new();
 /// ```
 #[proc_macro_attribute]
 pub fn enclave(attr: TokenStream, item: TokenStream) -> TokenStream {
-    let item: ItemStatic = parse_macro_input!(item as ItemStatic);
+    let attr = parse_macro_input!(attr as AttributeArgs);
+    let mut item: ItemStatic = parse_macro_input!(item as ItemStatic);
+
+    let mut section = None;
+    let mut checksum = Ident::new("Crc32c", Span::call_site());
+    let mut public_key = quote! { None };
+    let mut codec = Ident::new("Bincode", Span::call_site());
+    let mut version = quote! { 0 };
+    let mut backups = quote! { 0 };
+    let mut slots = quote! { 1 };
+    let mut init = None;
+    let mut krate = None;
+    let mut max_size = false;
+    let mut signed = false;
+    let mut key_span = None;
+    for arg in attr {
+        match arg {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("max_size") => {
+                max_size = true;
+            }
+            NestedMeta::Meta(Meta::Path(path)) if section.is_none() && path.get_ident().is_some() => {
+                section = path.get_ident().cloned();
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("checksum") => {
+                let variant = match &nv.lit {
+                    Lit::Str(s) if s.value() == "crc32c" => "Crc32c",
+                    Lit::Str(s) if s.value() == "xxhash64" => "XxHash64",
+                    Lit::Str(s) if s.value() == "sha256" => "Sha256",
+                    lit => {
+                        return error(
+                            lit,
+                            "checksum must be one of \"crc32c\", \"xxhash64\" or \"sha256\"",
+                        );
+                    }
+                };
+                checksum = Ident::new(variant, nv.lit.span());
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("codec") => {
+                let variant = match &nv.lit {
+                    Lit::Str(s) if s.value() == "bincode" => "Bincode",
+                    Lit::Str(s) if s.value() == "postcard" => "Postcard",
+                    Lit::Str(s) if s.value() == "cbor" => "Cbor",
+                    Lit::Str(s) if s.value() == "msgpack" => "MessagePack",
+                    Lit::Str(s) if s.value() == "json" => "Json",
+                    lit => {
+                        return error(
+                            lit,
+                            "codec must be one of \"bincode\", \"postcard\", \"cbor\", \"msgpack\" or \"json\"",
+                        );
+                    }
+                };
+                codec = Ident::new(variant, nv.lit.span());
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("version") => {
+                version = match &nv.lit {
+                    Lit::Int(int) if int.base10_parse::<u16>().is_ok() => quote! { #int },
+                    lit => {
+                        return error(lit, "version must be a u16");
+                    }
+                };
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("backups") => {
+                backups = match &nv.lit {
+                    Lit::Int(int) if int.base10_parse::<usize>().is_ok() => quote! { #int },
+                    lit => {
+                        return error(lit, "backups must be a usize");
+                    }
+                };
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("slots") => {
+                slots = match &nv.lit {
+                    Lit::Int(int) if int.base10_parse::<u8>().map_or(false, |n| n > 0) => {
+                        quote! { #int }
+                    }
+                    lit => {
+                        return error(lit, "slots must be between 1 and 255");
+                    }
+                };
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("init") => {
+                init = match &nv.lit {
+                    Lit::Str(s) => Some(s.clone()),
+                    lit => {
+                        return error(lit, "init must be a file path");
+                    }
+                };
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("crate") => {
+                krate = match &nv.lit {
+                    Lit::Str(s) => match s.parse::<syn::Path>() {
+                        Ok(path) => Some(quote! { #path }),
+                        Err(_) => return error(s, "crate must be a path"),
+                    },
+                    lit => return error(lit, "crate must be a path"),
+                };
+            }
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("public_key") => {
+                let key = match &nv.lit {
+                    Lit::Str(s) => parse_key(&s.value()),
+                    _ => None,
+                };
+                let key = match key {
+                    Some(key) => key,
+                    None => {
+                        return error(&nv.lit, "public_key must be 32 bytes of hex");
+                    }
+                };
+                public_key = quote! { Some([#(#key),*]) };
+                signed = true;
+                key_span = Some(nv.lit.span());
+            }
+            arg => {
+                return error(arg, "unexpected enclave argument");
+            }
+        }
+    }
+
+    let (section, section_span) = match section {
+        Some(ident) => match section_name(&ident) {
+            Ok(name) => (name, ident.span()),
+            Err(err) => return err.to_compile_error().into(),
+        },
+        None => {
+            return syn::Error::new(Span::call_site(), "enclave requires a section name")
+                .to_compile_error()
+                .into();
+        }
+    };
 
-    let (link_section, section) = if cfg!(target_os = "linux") {
-        (format!(".{}", attr), format!(".{}", attr))
-    } else if cfg!(target_os = "macos") {
-        (format!("__DATA,__{}", attr), format!("__{}", attr))
+    // the link section is chosen per target. The binary format itself is
+    // detected when the enclave is written, which only needs the bare name.
+    let elf_section = format!(".{}", section);
+    let macho_section = format!("__DATA,__{}", section);
+    let pe_section = format!(".{}", section);
+
+    // PE truncates longer names rather than refusing them, leaving an
+    // enclave that can never be located again. Only Windows builds care.
+    let pe_check = if section.len() > PE_SECTION_MAX {
+        let message = format!(
+            "section name must be at most {} bytes for Windows, PE keeps 8 including `.`",
+            PE_SECTION_MAX
+        );
+        quote_spanned! { section_span=> #[cfg(windows)] compile_error!(#message); }
     } else {
-        ("".to_string(), "".to_string())
+        quote! {}
     };
 
-    let segment = match item.ty.as_ref() {
-        Type::Path(path) => {
-            let seg = &path.path.segments;
-            seg.first().unwrap().clone()
+    if !matches!(item.ty.as_ref(), Type::Path(_)) {
+        return error(&item.ty, "enclave must be of type Enclave<T, SIZE>");
+    }
+
+    let krate = krate.unwrap_or_else(crate_path);
+
+    // `Enclave<T, _>` is sized from `T: MaxSize`, before anything looks at
+    // the size. Only a plain `Enclave` names its payload type for this.
+    let mut auto_size = false;
+    if let Type::Path(path) = item.ty.as_mut() {
+        let segment = path.path.segments.last_mut().unwrap();
+        if let PathArguments::AngleBracketed(generics) = &mut segment.arguments {
+            let mut args = generics.args.iter_mut();
+            if let (Some(GenericArgument::Type(payload)), Some(size)) = (args.next(), args.next()) {
+                if let GenericArgument::Type(Type::Infer(_)) = size {
+                    let required = quote! {
+                        #krate::max_size::required(
+                            #slots,
+                            <#payload as #krate::MaxSize>::MAX_SIZE,
+                            #signed,
+                        )
+                    };
+                    *size = GenericArgument::Const(parse_quote! { { #required } });
+                    auto_size = true;
+                }
+            }
         }
-        _ => {
-            item.ty
-                .span()
-                .unwrap()
-                .error("not sure how to handle this type")
-                .emit();
-            return TokenStream::new();
+    }
+
+    if (max_size || auto_size) && codec != "Bincode" {
+        return error(&codec, "payload sizes are only known for the bincode codec");
+    }
+
+    // the type is only known by name here, so payload type and size are
+    // taken from it through `EnclaveType`, which sees through aliases. The
+    // static is then retyped to carry its own locator.
+    let declared = &item.ty;
+    let ty = quote! { <#declared as #krate::EnclaveType>::Payload };
+    let size = quote! { <#declared as #krate::EnclaveType>::SIZE };
+    let locator = format_ident!("__EnclaveLocator_{}", item.ident);
+    let static_ty: Type = parse_quote! { #krate::Enclave<#ty, { #size }, #locator> };
+
+    // `Alias::new()` would build the enclave without the locator, a plain
+    // `new()` is rebuilt for the retyped static whatever path it took.
+    if let Expr::Call(call) = item.expr.as_ref() {
+        let is_new = match call.func.as_ref() {
+            Expr::Path(func) => func
+                .path
+                .segments
+                .last()
+                .map_or(false, |seg| seg.ident == "new"),
+            _ => false,
+        };
+        if is_new && call.args.is_empty() {
+            *item.expr = parse_quote! { <#static_ty>::new() };
         }
-    };
+    }
 
-    if segment.ident != Ident::new("Enclave", Span::call_site()) {
-        item.ty
-            .span()
-            .unwrap()
-            .error("enclave must be of type Enclave");
-        return TokenStream::new();
+    // seeded enclaves bake the init file in place of the given initializer,
+    // include_bytes also rebuilds us whenever it changes.
+    if let Some(init) = init {
+        if signed {
+            return error(&init, "init cannot seed an enclave with a public_key");
+        }
+        if let Some((variant, name)) = init_codec(&init.value()) {
+            if codec != variant {
+                let message = format!("init file is {0}, it needs codec = \"{0}\"", name);
+                return error(&init, &message);
+            }
+        }
+
+        let init = init.value();
+        let file = if std::path::Path::new(&init).is_absolute() {
+            quote! { #init }
+        } else {
+            quote! { concat!(env!("CARGO_MANIFEST_DIR"), "/", #init) }
+        };
+        *item.expr = parse_quote! { <#static_ty>::seeded(include_bytes!(#file)) };
     }
+    *item.ty = static_ty;
+    let vis = &item.vis;
 
-    let generics = match segment.arguments {
-        PathArguments::AngleBracketed(gens) => gens,
-        _ => return TokenStream::new(),
+    let size_check = if max_size {
+        quote! {
+            const _: () = assert!(
+                #size >= #krate::max_size::required(
+                    #slots,
+                    <#ty as #krate::MaxSize>::MAX_SIZE,
+                    #signed,
+                ),
+                "enclave is too small for the largest payload of its type",
+            );
+        }
+    } else {
+        quote! {}
     };
 
-    let ty = match generics.args.first().unwrap() {
-        GenericArgument::Type(ty) => ty,
-        _ => return TokenStream::new(),
+    // verifying needs the `signing` feature, which the program would
+    // otherwise only find out about when every payload is rejected.
+    let signing_check = match key_span {
+        Some(span) => quote_spanned! { span=> #krate::__require_signing!(); },
+        None => quote! {},
     };
 
     let output = quote! {
         #[no_mangle]
-        #[link_section = #link_section]
+        #[cfg_attr(any(target_os = "macos", target_os = "ios"), link_section = #macho_section)]
+        #[cfg_attr(windows, link_section = #pe_section)]
+        #[cfg_attr(not(any(target_os = "macos", target_os = "ios", windows)), link_section = #elf_section)]
         #item
+        #pe_check
+        #size_check
+        #signing_check
+
+        #[doc(hidden)]
+        #[allow(non_camel_case_types)]
+        #vis struct #locator;
 
-        impl binary_enclave::EnclaveLocator for #ty {
+        impl #krate::EnclaveLocator for #locator {
             const SECTION: &'static str = #section;
+            const CHECKSUM: #krate::Checksum = #krate::Checksum::#checksum;
+            const PUBLIC_KEY: Option<[u8; 32]> = #public_key;
+            const VERSION: u16 = #version;
+            const BACKUPS: usize = #backups;
+            const SLOTS: u8 = #slots;
+            type Codec = #krate::codec::#codec;
+
+            fn schema() -> u64 {
+                static SCHEMA: #krate::schema::Cached = #krate::schema::Cached::new();
+                SCHEMA.get::<#ty>()
+            }
         }
     };
 
     TokenStream::from(output)
 }
+
+/// Implement `MaxSize` for a struct or enum whose fields all implement it,
+/// giving the largest size bincode can serialize it to.
+#[proc_macro_derive(MaxSize)]
+pub fn derive_max_size(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match max_size::derive(input) {
+        Ok(output) => TokenStream::from(output),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Codec an init file is written with as told by its extension, both as
+/// its `codec` variant and name. Bincode and postcard have no extension of
+/// their own, such files are taken to match whatever codec is in use.
+fn init_codec(path: &str) -> Option<(&'static str, &'static str)> {
+    match std::path::Path::new(path).extension()?.to_str()? {
+        "json" => Some(("Json", "json")),
+        "cbor" => Some(("Cbor", "cbor")),
+        "msgpack" | "mpk" => Some(("MessagePack", "msgpack")),
+        _ => None,
+    }
+}
+
+/// Path of `binary_enclave` as the calling crate depends on it, which may
+/// have renamed it.
+fn crate_path() -> TokenStream2 {
+    match crate_name("binary_enclave") {
+        Ok(FoundCrate::Name(name)) => {
+            let name = Ident::new(&name, Span::call_site());
+            quote! { ::#name }
+        }
+        // examples and doctests of binary_enclave itself are reported as the
+        // crate, but only reach it as an external one.
+        Ok(FoundCrate::Itself) | Err(_) => quote! { ::binary_enclave },
+    }
+}
+
+fn section_name(ident: &Ident) -> syn::Result<String> {
+    let name = ident.to_string();
+    if !name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+        return Err(syn::Error::new(
+            ident.span(),
+            "section name may only use ASCII letters, digits and `_`",
+        ));
+    }
+    if name.len() > MACHO_SECTION_MAX {
+        return Err(syn::Error::new(
+            ident.span(),
+            format!(
+                "section name must be at most {} bytes, Mach-O keeps 16 including `__`",
+                MACHO_SECTION_MAX
+            ),
+        ));
+    }
+    Ok(name)
+}
+
+fn error<T: ToTokens>(tokens: T, message: &str) -> TokenStream {
+    syn::Error::new_spanned(tokens, message)
+        .to_compile_error()
+        .into()
+}
+
+fn parse_key(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).ok())
+        .collect()
+}
@@ -0,0 +1,74 @@
+//! Declarations `#[enclave]` rejects with an error of its own, each of
+//! which would compile with the marked problem fixed.
+//!
+//! Missing section name:
+//!
+//! ```compile_fail
+//! use binary_enclave::{enclave, Enclave};
+//!
+//! #[enclave]
+//! static CONFIG: Enclave<u32, 64> = Enclave::new();
+//! ```
+//!
+//! Section name that is not an identifier:
+//!
+//! ```compile_fail
+//! use binary_enclave::{enclave, Enclave};
+//!
+//! #[enclave("appconf")]
+//! static CONFIG: Enclave<u32, 64> = Enclave::new();
+//! ```
+//!
+//! Section name outside of ASCII:
+//!
+//! ```compile_fail
+//! use binary_enclave::{enclave, Enclave};
+//!
+//! #[enclave(réglages)]
+//! static CONFIG: Enclave<u32, 64> = Enclave::new();
+//! ```
+//!
+//! Section name longer than Mach-O keeps:
+//!
+//! ```compile_fail
+//! use binary_enclave::{enclave, Enclave};
+//!
+//! #[enclave(application_config)]
+//! static CONFIG: Enclave<u32, 64> = Enclave::new();
+//! ```
+//!
+//! Unknown argument:
+//!
+//! ```compile_fail
+//! use binary_enclave::{enclave, Enclave};
+//!
+//! #[enclave(appconf, colour = "red")]
+//! static CONFIG: Enclave<u32, 64> = Enclave::new();
+//! ```
+//!
+//! Invalid argument value:
+//!
+//! ```compile_fail
+//! use binary_enclave::{enclave, Enclave};
+//!
+//! #[enclave(appconf, checksum = "md5")]
+//! static CONFIG: Enclave<u32, 64> = Enclave::new();
+//! ```
+//!
+//! Static that is not an `Enclave`:
+//!
+//! ```compile_fail
+//! use binary_enclave::enclave;
+//!
+//! #[enclave(appconf)]
+//! static CONFIG: u32 = 0;
+//! ```
+//!
+//! Static whose type is not a path:
+//!
+//! ```compile_fail
+//! use binary_enclave::enclave;
+//!
+//! #[enclave(appconf)]
+//! static CONFIG: [u8; 64] = [0; 64];
+//! ```
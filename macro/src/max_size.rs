@@ -0,0 +1,45 @@
+//! `#[derive(MaxSize)]`, sizing types the way bincode serializes them.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{parse_quote, Data, DeriveInput, Fields, GenericParam};
+
+pub(crate) fn derive(mut input: DeriveInput) -> syn::Result<TokenStream> {
+    let krate = crate::crate_path();
+    for param in &mut input.generics.params {
+        if let GenericParam::Type(ty) = param {
+            ty.bounds.push(parse_quote! { #krate::MaxSize });
+        }
+    }
+
+    let size = match &input.data {
+        Data::Struct(data) => fields_size(&krate, &data.fields),
+        // bincode prefixes the variant with its u32 index.
+        Data::Enum(data) => {
+            let variants = data
+                .variants
+                .iter()
+                .map(|var| fields_size(&krate, &var.fields));
+            quote! { 4 + #krate::max_size::largest(&[#(#variants),*]) }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "MaxSize cannot be derived for unions",
+            ))
+        }
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::MaxSize for #name #ty_generics #where_clause {
+            const MAX_SIZE: usize = #size;
+        }
+    })
+}
+
+fn fields_size(krate: &TokenStream, fields: &Fields) -> TokenStream {
+    let types = fields.iter().map(|field| &field.ty);
+    quote! { 0 #(+ <#types as #krate::MaxSize>::MAX_SIZE)* }
+}
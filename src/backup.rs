@@ -0,0 +1,82 @@
+//! History of previous enclave payloads.
+//!
+//! Before a write replaces a payload, the old header and payload are kept
+//! in `.<binary>.<section>.backup/` beside the binary, numbered in the
+//! order they were replaced. Rolling back restores the newest of them.
+
+use crate::error::{Error, Result};
+use crate::header::{Header, HEADER_LEN};
+use crate::raw::Section;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A previous payload of an enclave.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    /// Position in the history, higher is newer.
+    pub seq: u64,
+    /// Header the payload was stored with.
+    pub header: Header,
+    /// The stored payload itself.
+    pub payload: Vec<u8>,
+    path: PathBuf,
+}
+
+impl Backup {
+    /// Forget this backup, such as once it has been restored.
+    pub(crate) fn remove(self) -> Result<()> {
+        Ok(fs::remove_file(self.path)?)
+    }
+}
+
+/// Keep `section`'s current payload in the history of the binary at
+/// `path`, dropping the oldest entries beyond `keep`.
+pub(crate) fn save(path: &Path, section: &Section, keep: usize) -> Result<()> {
+    let payload = section.payload().ok_or(Error::PayloadChecksum)?;
+    let dir = history_dir(path, &section.name)?;
+    fs::create_dir_all(&dir)?;
+
+    let history = list(path, &section.name)?;
+    let seq = history.last().map(|backup| backup.seq + 1).unwrap_or(0);
+    let mut bytes = section.header.to_bytes().to_vec();
+    bytes.extend_from_slice(payload);
+    fs::write(dir.join(format!("{:08}", seq)), bytes)?;
+
+    let stale = (history.len() + 1).saturating_sub(keep);
+    for backup in history.into_iter().take(stale) {
+        backup.remove()?;
+    }
+    Ok(())
+}
+
+/// Every backup of `name` for the binary at `path`, oldest first.
+pub(crate) fn list(path: &Path, name: &str) -> Result<Vec<Backup>> {
+    let dir = history_dir(path, name)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut history = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let seq = match path.file_name().and_then(|n| n.to_str()?.parse().ok()) {
+            Some(seq) => seq,
+            None => continue,
+        };
+        let bytes = fs::read(&path)?;
+        let header = Header::parse(&bytes)?;
+        history.push(Backup {
+            seq,
+            header,
+            payload: bytes[HEADER_LEN..].to_vec(),
+            path,
+        });
+    }
+    history.sort_by_key(|backup| backup.seq);
+    Ok(history)
+}
+
+fn history_dir(path: &Path, name: &str) -> Result<PathBuf> {
+    let file_name = path.file_name().ok_or(Error::BinaryNotLocated)?;
+    Ok(path.with_file_name(format!(".{}.{}.backup", file_name.to_string_lossy(), name)))
+}
@@ -0,0 +1,149 @@
+use binary_enclave::raw::{self, Section};
+use binary_enclave::Error;
+use std::process::exit;
+
+const USAGE: &str = "usage: enclave <command> <binary> [args]
+
+commands:
+  list     <binary>                    list enclave sections
+  info     <binary> <section>          show capacity and used length
+  verify   <binary> [section]          verify payload checksums
+  dump     <binary> <section>          hex dump the raw payload
+  write    [--keep <n>] <binary> <section> <file>
+                                       replace the payload from a file,
+                                       keeping <n> previous payloads
+  history  <binary> <section>          list previous payloads kept
+  rollback <binary> <section>          restore the newest previous payload";
+
+// inspects and edits enclaves without the host program's cooperation.
+// payloads are handled raw, as they were serialized by the host program.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    let res = match args.as_slice() {
+        ["list", bin] => list(bin),
+        ["info", bin, name] => info(bin, name),
+        ["verify", bin] => verify(bin, None),
+        ["verify", bin, name] => verify(bin, Some(*name)),
+        ["dump", bin, name] => dump(bin, name),
+        ["write", bin, name, file] => write(bin, name, file, 0),
+        ["write", "--keep", keep, bin, name, file] => match keep.parse() {
+            Ok(keep) => write(bin, name, file, keep),
+            Err(_) => Err(format!("invalid --keep count: {}", keep).into()),
+        },
+        ["history", bin, name] => history(bin, name),
+        ["rollback", bin, name] => rollback(bin, name),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = res {
+        eprintln!("enclave: {}", e);
+        exit(1);
+    }
+}
+
+type Result<T = ()> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn list(bin: &str) -> Result {
+    let data = std::fs::read(bin)?;
+    println!("{:<16} {:>10} {:>10}  STATUS", "SECTION", "CAPACITY", "USED");
+    for sec in raw::sections(&data)? {
+        println!("{:<16} {:>10} {:>10}  {}", sec.name, sec.capacity, sec.header.len, status(&sec));
+    }
+    Ok(())
+}
+
+fn info(bin: &str, name: &str) -> Result {
+    let data = std::fs::read(bin)?;
+    let sec = raw::section(&data, name)?;
+    println!("section:  {}", sec.name);
+    println!("capacity: {}", sec.capacity);
+    println!("slot:     {} of {}, generation {}", sec.slot, sec.header.slot_count(), sec.header.generation);
+    println!("used:     {}", sec.header.len);
+    println!("version:  {}", sec.header.version);
+    println!("flags:    {:#06x}", sec.header.flags);
+    println!("codec:    {}", sec.header.codec);
+    println!("payload:  v{}", sec.header.payload_version);
+    println!("schema:   {:016x}", sec.header.schema);
+    match sec.checksum() {
+        Some(checksum) => println!("checksum: {:?} {}", checksum, hex(&sec.header.checksum)),
+        None => println!("checksum: none"),
+    }
+    println!("status:   {}", status(&sec));
+    Ok(())
+}
+
+fn verify(bin: &str, name: Option<&str>) -> Result {
+    let data = std::fs::read(bin)?;
+    let sections = match name {
+        Some(name) => vec![raw::section(&data, name)?],
+        None => raw::sections(&data)?,
+    };
+
+    let mut failed = false;
+    for sec in &sections {
+        println!("{}: {}", sec.name, status(sec));
+        failed |= !sec.header.is_empty() && sec.verify().is_err();
+    }
+    if failed {
+        return Err("checksum verification failed".into());
+    }
+    Ok(())
+}
+
+fn dump(bin: &str, name: &str) -> Result {
+    let data = std::fs::read(bin)?;
+    let sec = raw::section(&data, name)?;
+    let payload = sec.payload().ok_or("payload length exceeds section")?;
+
+    for (idx, line) in payload.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = line
+            .iter()
+            .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+            .collect();
+        println!("{:08x}  {:<47}  |{}|", idx * 16, hex.join(" "), text);
+    }
+    Ok(())
+}
+
+fn write(bin: &str, name: &str, file: &str, keep: usize) -> Result {
+    let payload = std::fs::read(file)?;
+    let data = std::fs::read(bin)?;
+    let sec = raw::section(&data, name)?;
+    let checksum = sec.checksum().unwrap_or_default();
+    let written = raw::write_to(bin, name, &payload, checksum, &sec.header, keep)?;
+    println!("{}: wrote {} bytes", name, written);
+    Ok(())
+}
+
+fn history(bin: &str, name: &str) -> Result {
+    println!("{:>8} {:>10}  CHECKSUM", "SEQ", "USED");
+    for backup in raw::history(bin, name)? {
+        let header = backup.header;
+        println!("{:>8} {:>10}  {}", backup.seq, header.len, hex(&header.checksum));
+    }
+    Ok(())
+}
+
+fn rollback(bin: &str, name: &str) -> Result {
+    let written = raw::rollback(bin, name)?;
+    println!("{}: restored {} bytes", name, written);
+    Ok(())
+}
+
+fn status(sec: &Section) -> String {
+    match sec.verify() {
+        Ok(_) => "ok".to_string(),
+        Err(Error::PayloadEmpty) => "empty".to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,111 @@
+//! Checksum algorithms an enclave payload can be verified with.
+//!
+//! Each algorithm is fully specified, so a checksum written by one build
+//! verifies under any other, whatever compiler or language it uses.
+
+use crate::error::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::hash::Hasher;
+use twox_hash::XxHash64;
+
+/// Checksum algorithm recorded in the enclave header.
+///
+/// Chosen per enclave with `#[enclave(name, checksum = "sha256")]`,
+/// defaulting to CRC32C.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// CRC-32C (Castagnoli), stored little-endian.
+    Crc32c,
+    /// xxHash64 with a zero seed, stored little-endian.
+    XxHash64,
+    /// SHA-256 digest.
+    Sha256,
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Checksum::Crc32c
+    }
+}
+
+impl Checksum {
+    /// Algorithm id stored in the header.
+    pub const fn id(self) -> u8 {
+        match self {
+            Checksum::Crc32c => 2,
+            Checksum::XxHash64 => 3,
+            Checksum::Sha256 => 4,
+        }
+    }
+
+    /// Algorithm for a header id. Id `1` was the unstable `DefaultHasher`
+    /// and is no longer verifiable.
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            2 => Ok(Checksum::Crc32c),
+            3 => Ok(Checksum::XxHash64),
+            4 => Ok(Checksum::Sha256),
+            other => Err(Error::UnsupportedChecksum(other)),
+        }
+    }
+
+    /// Checksum `payload`, giving the bytes as stored in the header.
+    pub fn digest(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Checksum::Crc32c => crc32c::crc32c(payload).to_le_bytes().to_vec(),
+            Checksum::XxHash64 => {
+                let mut hasher = XxHash64::with_seed(0);
+                hasher.write(payload);
+                hasher.finish().to_le_bytes().to_vec()
+            }
+            Checksum::Sha256 => Sha256::digest(payload).to_vec(),
+        }
+    }
+}
+
+/// CRC-32C computed a bit at a time, for checksums needed at compile time.
+pub(crate) const fn crc32c_const(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    let mut idx = 0;
+    while idx < bytes.len() {
+        crc ^= bytes[idx] as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+            bit += 1;
+        }
+        idx += 1;
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_vectors() {
+        assert_eq!(Checksum::Crc32c.digest(b"123456789"), 0xe306_9283u32.to_le_bytes());
+        assert_eq!(Checksum::XxHash64.digest(b""), 0xef46_db37_51d8_e999u64.to_le_bytes());
+        let sha = Checksum::Sha256.digest(b"abc");
+        assert_eq!(sha[..4], [0xba, 0x78, 0x16, 0xbf]);
+        assert_eq!(sha[28..], [0xf2, 0x00, 0x15, 0xad]);
+    }
+
+    #[test]
+    fn const_crc32c_matches_crc32c() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+        for len in [0, 1, 9, 63, 64, 1000] {
+            assert_eq!(crc32c_const(&data[..len]), crc32c::crc32c(&data[..len]));
+        }
+        assert_eq!(crc32c_const(b"123456789"), 0xe306_9283);
+    }
+
+    #[test]
+    fn ids_round_trip() {
+        for checksum in [Checksum::Crc32c, Checksum::XxHash64, Checksum::Sha256] {
+            assert_eq!(Checksum::from_id(checksum.id()).unwrap(), checksum);
+        }
+        assert!(matches!(Checksum::from_id(1), Err(Error::UnsupportedChecksum(1))));
+    }
+}
@@ -0,0 +1,219 @@
+//! Serialization formats an enclave payload can be stored in.
+//!
+//! The codec is chosen per enclave with `#[enclave(name, codec = "json")]`
+//! and its id recorded in the header. Bincode is always available, the
+//! others sit behind the cargo feature of the same name.
+
+use crate::error::{Error, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A serialization format for enclave payloads.
+pub trait EnclaveCodec {
+    /// Codec id stored in the header.
+    const ID: u8;
+
+    /// Serialize `value` into its stored form.
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>>;
+
+    /// Deserialize a value from its stored form.
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+}
+
+/// [bincode](https://docs.rs/bincode), compact but Rust specific.
+pub struct Bincode;
+
+impl EnclaveCodec for Bincode {
+    const ID: u8 = 0;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|e| Error::PayloadEncoding(Box::new(e)))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        bincode::deserialize(bytes).map_err(|e| Error::PayloadDecoding(Box::new(e)))
+    }
+}
+
+/// [postcard](https://docs.rs/postcard), compact and `no_std` friendly.
+#[cfg(feature = "postcard")]
+pub struct Postcard;
+
+#[cfg(feature = "postcard")]
+impl EnclaveCodec for Postcard {
+    const ID: u8 = 1;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        postcard::to_stdvec(value).map_err(|e| Error::PayloadEncoding(Box::new(e)))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        postcard::from_bytes(bytes).map_err(|e| Error::PayloadDecoding(Box::new(e)))
+    }
+}
+
+/// [CBOR](https://cbor.io), self describing.
+#[cfg(feature = "cbor")]
+pub struct Cbor;
+
+#[cfg(feature = "cbor")]
+impl EnclaveCodec for Cbor {
+    const ID: u8 = 2;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        serde_cbor::to_vec(value).map_err(|e| Error::PayloadEncoding(Box::new(e)))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_cbor::from_slice(bytes).map_err(|e| Error::PayloadDecoding(Box::new(e)))
+    }
+}
+
+/// [MessagePack](https://msgpack.org), self describing with named fields.
+#[cfg(feature = "msgpack")]
+pub struct MessagePack;
+
+#[cfg(feature = "msgpack")]
+impl EnclaveCodec for MessagePack {
+    const ID: u8 = 3;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        rmp_serde::to_vec_named(value).map_err(|e| Error::PayloadEncoding(Box::new(e)))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        rmp_serde::from_slice(bytes).map_err(|e| Error::PayloadDecoding(Box::new(e)))
+    }
+}
+
+/// JSON, readable by just about anything.
+#[cfg(feature = "json")]
+pub struct Json;
+
+#[cfg(feature = "json")]
+impl EnclaveCodec for Json {
+    const ID: u8 = 4;
+
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|e| Error::PayloadEncoding(Box::new(e)))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|e| Error::PayloadDecoding(Box::new(e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Checksum;
+    use crate::header::Header;
+    use crate::EnclaveLocator;
+    use serde::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+    struct Payload {
+        name: String,
+        port: u16,
+        weight: f64,
+        tags: Vec<String>,
+        limits: BTreeMap<String, u32>,
+        fallback: Option<Box<Payload>>,
+        mode: Mode,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        Off,
+        Fixed(u32),
+        Range { low: i64, high: i64 },
+    }
+
+    impl Default for Mode {
+        fn default() -> Self {
+            Mode::Off
+        }
+    }
+
+    fn payload() -> Payload {
+        let mut limits = BTreeMap::new();
+        limits.insert("conns".to_string(), 64);
+        Payload {
+            name: "appconf".into(),
+            port: 8080,
+            weight: 0.5,
+            tags: vec!["a".into(), "b".into()],
+            limits,
+            fallback: Some(Box::new(Payload {
+                mode: Mode::Fixed(3),
+                ..Payload::default()
+            })),
+            mode: Mode::Range { low: -1, high: 1 },
+        }
+    }
+
+    fn round_trip<C: EnclaveCodec>() {
+        let bytes = C::encode(&payload()).unwrap();
+        assert_eq!(C::decode::<Payload>(&bytes).unwrap(), payload());
+        assert!(matches!(C::decode::<Payload>(&bytes[..bytes.len() / 2]), Err(Error::PayloadDecoding(_))));
+    }
+
+    #[test]
+    fn bincode_round_trips() {
+        round_trip::<Bincode>();
+    }
+
+    #[cfg(feature = "postcard")]
+    #[test]
+    fn postcard_round_trips() {
+        round_trip::<Postcard>();
+    }
+
+    #[cfg(feature = "cbor")]
+    #[test]
+    fn cbor_round_trips() {
+        round_trip::<Cbor>();
+    }
+
+    #[cfg(feature = "msgpack")]
+    #[test]
+    fn msgpack_round_trips() {
+        round_trip::<MessagePack>();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_round_trips() {
+        round_trip::<Json>();
+    }
+
+    #[test]
+    fn ids_are_stable() {
+        assert_eq!(Bincode::ID, 0);
+        #[cfg(feature = "postcard")]
+        assert_eq!(Postcard::ID, 1);
+        #[cfg(feature = "cbor")]
+        assert_eq!(Cbor::ID, 2);
+        #[cfg(feature = "msgpack")]
+        assert_eq!(MessagePack::ID, 3);
+        #[cfg(feature = "json")]
+        assert_eq!(Json::ID, 4);
+    }
+
+    struct Appconf;
+
+    impl EnclaveLocator for Appconf {
+        const SECTION: &'static str = "appconf";
+        type Codec = Bincode;
+    }
+
+    #[test]
+    fn other_codec_is_rejected() {
+        let pack = Bincode::encode(&payload()).unwrap();
+        let header = Header::new(&pack, Checksum::Crc32c, 4, 0);
+        assert!(matches!(crate::open::<Appconf>(&header, &pack), Err(Error::CodecMismatch(4))));
+
+        let header = Header::new(&pack, Checksum::Crc32c, Bincode::ID, 0);
+        assert_eq!(crate::open::<Appconf>(&header, &pack).unwrap(), &pack[..]);
+    }
+}
@@ -0,0 +1,73 @@
+//! Authenticated encryption of enclave payloads.
+//!
+//! Sealed payloads are stored as a random 96-bit nonce followed by the
+//! ChaCha20-Poly1305 ciphertext and tag. The section name is bound in as
+//! associated data, so a payload cannot be moved between enclaves.
+
+use crate::error::{Error, Result};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key as CipherKey, Nonce};
+use std::convert::TryInto;
+
+/// Key used to seal and open enclave payloads.
+pub type Key = [u8; 32];
+
+const NONCE_LEN: usize = 12;
+
+/// Encrypt `msg` under `key`, giving nonce and ciphertext together.
+pub(crate) fn seal(key: &Key, section: &str, msg: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).map_err(std::io::Error::from)?;
+
+    let cipher = ChaCha20Poly1305::new(&CipherKey::from(*key));
+    let payload = Payload { msg, aad: section.as_bytes() };
+    let sealed = cipher
+        .encrypt(&Nonce::from(nonce), payload)
+        .map_err(|e| Error::PayloadEncoding(Box::new(e)))?;
+
+    let mut out = nonce.to_vec();
+    out.extend(sealed);
+    Ok(out)
+}
+
+/// Authenticate and decrypt a payload produced by `seal`.
+pub(crate) fn open(key: &Key, section: &str, sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN {
+        return Err(Error::PayloadAuthentication);
+    }
+    let (nonce, msg) = sealed.split_at(NONCE_LEN);
+    let nonce: [u8; NONCE_LEN] = nonce.try_into().unwrap();
+
+    let cipher = ChaCha20Poly1305::new(&CipherKey::from(*key));
+    let payload = Payload { msg, aad: section.as_bytes() };
+    cipher
+        .decrypt(&Nonce::from(nonce), payload)
+        .map_err(|_| Error::PayloadAuthentication)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seal_and_open() {
+        let key = [7; 32];
+        let sealed = seal(&key, "appconfig", b"payload").unwrap();
+        assert_eq!(sealed.len(), NONCE_LEN + b"payload".len() + 16);
+        assert_eq!(open(&key, "appconfig", &sealed).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn open_rejects_wrong_key_or_section() {
+        let key = [7; 32];
+        let sealed = seal(&key, "appconfig", b"payload").unwrap();
+        for (key, section, sealed) in [
+            (&[8; 32], "appconfig", &sealed[..]),
+            (&key, "other", &sealed[..]),
+            (&key, "appconfig", &sealed[..4]),
+        ] {
+            let opened = open(key, section, sealed);
+            assert!(matches!(opened, Err(Error::PayloadAuthentication)));
+        }
+    }
+}
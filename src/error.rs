@@ -16,12 +16,68 @@ pub enum Error {
 
     /// Error occured during Enclave deserialization. Binary tampering?
     #[error("Payload decoding error")]
-    PayloadDecoding(#[from] Box<bincode::ErrorKind>),
+    PayloadDecoding(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// Error occured serializing a payload for the Enclave.
+    #[error("Payload encoding error")]
+    PayloadEncoding(#[source] Box<dyn std::error::Error + Send + Sync>),
+
+    /// Payload was written for a different layout of the payload type.
+    #[error("Payload schema mismatch. {:016x} != {:016x}.", found, expected)]
+    SchemaMismatch { expected: u64, found: u64 },
+
+    /// Payload was written for another version of the payload type.
+    #[error("Payload version {} requires migration", .0)]
+    PayloadVersion(u16),
+
+    /// No migration is registered from this payload version.
+    #[error("No migration from payload version {}", .0)]
+    MigrationMissing(u16),
+
+    /// Payload was stored with a different codec than the Enclave uses.
+    #[error("Payload encoded with codec {}", .0)]
+    CodecMismatch(u8),
 
     /// Payload checksum does not match payload. Binary tampering?
     #[error("Payload checksum error")]
     PayloadChecksum,
 
+    /// Encrypted payload failed authentication. Wrong key or tampering?
+    #[error("Payload authentication error")]
+    PayloadAuthentication,
+
+    /// Payload is encrypted and must be read with `decode_encrypted`.
+    #[error("Payload is encrypted")]
+    PayloadEncrypted,
+
+    /// Payload signature is missing or does not match the public key.
+    #[error("Payload signature invalid")]
+    SignatureInvalid,
+
+    /// Enclave has not been written to yet.
+    #[error("Payload empty")]
+    PayloadEmpty,
+
+    /// Section does not start with an enclave header.
+    #[error("Enclave header not recognised")]
+    HeaderMagic,
+
+    /// Enclave was written with a newer format than we understand.
+    #[error("Unsupported enclave format version {}", .0)]
+    UnsupportedVersion(u16),
+
+    /// Enclave was checksummed with an algorithm we do not know.
+    #[error("Unsupported checksum algorithm {}", .0)]
+    UnsupportedChecksum(u8),
+
+    /// Enclave changed on disk since it was read, the write was abandoned.
+    #[error("Enclave changed since it was read")]
+    WriteConflict,
+
+    /// No previous payload is left to roll back to.
+    #[error("No backup to roll back to")]
+    NoBackup,
+
     /// Failed to interpret binary. Simply put, this should never happen.
     #[error("Binary decoding error")]
     BinaryDecoding(#[from] goblin::error::Error),
@@ -30,6 +86,10 @@ pub enum Error {
     #[error("File Handling Error")]
     File(#[from] std::io::Error),
 
+    /// The binary is not in a format we know how to patch.
+    #[error("Unsupported binary format")]
+    UnsupportedBinary,
+
     /// The binary could not be located
     #[error("Binary not located")]
     BinaryNotLocated,
@@ -0,0 +1,286 @@
+//! On-disk header preceding every enclave payload.
+//!
+//! The layout is fixed and little-endian regardless of the host, so tools
+//! built for one platform can read enclaves of another.
+//!
+//! | offset | size | field                 |
+//! |--------|------|-----------------------|
+//! | 0      | 4    | magic, `ENCL`         |
+//! | 4      | 2    | format version        |
+//! | 6      | 2    | flags                 |
+//! | 8      | 8    | payload length        |
+//! | 16     | 1    | checksum algorithm id |
+//! | 17     | 1    | codec id              |
+//! | 18     | 2    | payload version       |
+//! | 20     | 1    | slot count            |
+//! | 21     | 1    | reserved, zero        |
+//! | 22     | 2    | slot generation       |
+//! | 24     | 8    | schema fingerprint    |
+//! | 32     | 32   | checksum, zero padded |
+
+use crate::checksum::{crc32c_const, Checksum};
+use crate::error::{Error, Result};
+use std::convert::TryInto;
+
+/// Magic bytes every enclave section starts with.
+pub const MAGIC: [u8; 4] = *b"ENCL";
+
+/// Format version written by this build.
+pub const VERSION: u16 = 1;
+
+/// Size of the header in bytes.
+pub const HEADER_LEN: usize = 64;
+
+/// Checksum algorithm id of an enclave never written to.
+pub const CHECKSUM_NONE: u8 = 0;
+
+/// Flag set when the payload is sealed with ChaCha20-Poly1305.
+pub const FLAG_ENCRYPTED: u16 = 1;
+
+/// Flag set when the payload is followed by an Ed25519 signature.
+pub const FLAG_SIGNED: u16 = 1 << 1;
+
+/// Header of an enclave split into `slots` that has not been written yet.
+/// It records the enclave's codec and payload version, so payloads written
+/// by tools that only see the binary are stored the way the program reads
+/// them.
+pub(crate) const fn empty(slots: u8, codec: u8, payload_version: u16) -> [u8; HEADER_LEN] {
+    let mut header = [0; HEADER_LEN];
+    header[0] = MAGIC[0];
+    header[1] = MAGIC[1];
+    header[2] = MAGIC[2];
+    header[3] = MAGIC[3];
+    header[4] = VERSION as u8;
+    header[5] = (VERSION >> 8) as u8;
+    header[17] = codec;
+    header[18] = payload_version as u8;
+    header[19] = (payload_version >> 8) as u8;
+    header[20] = slots;
+    header
+}
+
+/// Header of an enclave seeded with `payload` at compile time, checksummed
+/// with CRC32C. The schema is left unchecked as it cannot be traced then.
+pub(crate) const fn seeded(
+    slots: u8,
+    codec: u8,
+    payload_version: u16,
+    payload: &[u8],
+) -> [u8; HEADER_LEN] {
+    let mut header = empty(slots, codec, payload_version);
+    let len = (payload.len() as u64).to_le_bytes();
+    let mut idx = 0;
+    while idx < len.len() {
+        header[8 + idx] = len[idx];
+        idx += 1;
+    }
+
+    header[16] = Checksum::Crc32c.id();
+
+    let checksum = crc32c_const(payload).to_le_bytes();
+    let mut idx = 0;
+    while idx < checksum.len() {
+        header[32 + idx] = checksum[idx];
+        idx += 1;
+    }
+    header
+}
+
+/// Decoded enclave header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Format version the enclave was written with.
+    pub version: u16,
+    /// Format flags, see `FLAG_ENCRYPTED` and `FLAG_SIGNED`.
+    pub flags: u16,
+    /// Length of the payload following the header.
+    pub len: u64,
+    /// Algorithm the checksum was computed with, see `Checksum::id`.
+    pub algorithm: u8,
+    /// Codec the payload was serialized with, see `EnclaveCodec::ID`.
+    pub codec: u8,
+    /// Version of the payload type, see `Migrations`.
+    pub payload_version: u16,
+    /// Slots the enclave is split into, see `Header::slot_count`.
+    pub slots: u8,
+    /// Generation of the slot, the newest valid slot is used.
+    pub generation: u16,
+    /// Fingerprint of the payload type, zero if unknown.
+    pub schema: u64,
+    /// Checksum of the payload, zero padded.
+    pub checksum: [u8; 32],
+}
+
+impl Header {
+    /// Header for a payload written by this build.
+    pub(crate) fn new(payload: &[u8], checksum: Checksum, codec: u8, flags: u16) -> Self {
+        let digest = checksum.digest(payload);
+        let mut sum = [0; 32];
+        sum[..digest.len()].copy_from_slice(&digest);
+        Self {
+            version: VERSION,
+            flags,
+            len: payload.len() as u64,
+            algorithm: checksum.id(),
+            codec,
+            payload_version: 0,
+            slots: 1,
+            generation: 0,
+            schema: 0,
+            checksum: sum,
+        }
+    }
+
+    /// Parse the header at the start of `bytes`, rejecting anything that is
+    /// not an enclave or was written by a newer format version.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let bytes = bytes.get(0..HEADER_LEN).ok_or(Error::HeaderMagic)?;
+        if bytes[0..4] != MAGIC {
+            return Err(Error::HeaderMagic);
+        }
+
+        let version = u16::from_le_bytes(bytes[4..6].try_into().unwrap());
+        if version == 0 || version > VERSION {
+            return Err(Error::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            version,
+            flags: u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+            len: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            algorithm: bytes[16],
+            codec: bytes[17],
+            payload_version: u16::from_le_bytes(bytes[18..20].try_into().unwrap()),
+            slots: bytes[20],
+            generation: u16::from_le_bytes(bytes[22..24].try_into().unwrap()),
+            schema: u64::from_le_bytes(bytes[24..32].try_into().unwrap()),
+            checksum: bytes[32..64].try_into().unwrap(),
+        })
+    }
+
+    /// Serialize the header into its on-disk form.
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut bytes = [0; HEADER_LEN];
+        bytes[0..4].copy_from_slice(&MAGIC);
+        bytes[4..6].copy_from_slice(&self.version.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.flags.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.len.to_le_bytes());
+        bytes[16] = self.algorithm;
+        bytes[17] = self.codec;
+        bytes[18..20].copy_from_slice(&self.payload_version.to_le_bytes());
+        bytes[20] = self.slots;
+        bytes[22..24].copy_from_slice(&self.generation.to_le_bytes());
+        bytes[24..32].copy_from_slice(&self.schema.to_le_bytes());
+        bytes[32..64].copy_from_slice(&self.checksum);
+        bytes
+    }
+
+    /// Number of slots the enclave is split into, at least one.
+    pub fn slot_count(&self) -> usize {
+        self.slots.max(1) as usize
+    }
+
+    /// Whether this slot was written after `other`. Generations wrap, so
+    /// this only holds for slots written shortly after one another.
+    pub fn is_newer(&self, other: &Header) -> bool {
+        (self.generation.wrapping_sub(other.generation) as i16) > 0
+    }
+
+    /// Whether the payload is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    /// Whether the payload is followed by a signature.
+    pub fn is_signed(&self) -> bool {
+        self.flags & FLAG_SIGNED != 0
+    }
+
+    /// Whether nothing has been written to this enclave yet.
+    pub fn is_empty(&self) -> bool {
+        self.algorithm == CHECKSUM_NONE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header() -> Header {
+        Header {
+            flags: FLAG_SIGNED,
+            payload_version: 3,
+            slots: 2,
+            generation: 7,
+            schema: 0x0123_4567_89ab_cdef,
+            ..Header::new(b"payload", Checksum::Sha256, 2, 0)
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        let header = header();
+        assert_eq!(Header::parse(&header.to_bytes()).unwrap(), header);
+    }
+
+    #[test]
+    fn layout_is_little_endian() {
+        let bytes = header().to_bytes();
+        assert_eq!(&bytes[0..4], b"ENCL");
+        assert_eq!(&bytes[4..6], &[1, 0]);
+        assert_eq!(&bytes[6..8], &[2, 0]);
+        assert_eq!(&bytes[8..16], &[7, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[16..21], &[4, 2, 3, 0, 2]);
+        assert_eq!(bytes[21], 0);
+        assert_eq!(&bytes[22..24], &[7, 0]);
+        assert_eq!(&bytes[24..32], &[0xef, 0xcd, 0xab, 0x89, 0x67, 0x45, 0x23, 0x01]);
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_short_input() {
+        let mut bytes = header().to_bytes();
+        assert!(matches!(Header::parse(&bytes[..HEADER_LEN - 1]), Err(Error::HeaderMagic)));
+        bytes[0] = b'X';
+        assert!(matches!(Header::parse(&bytes), Err(Error::HeaderMagic)));
+    }
+
+    #[test]
+    fn rejects_unsupported_versions() {
+        let mut bytes = header().to_bytes();
+        for version in [0, VERSION + 1] {
+            bytes[4..6].copy_from_slice(&version.to_le_bytes());
+            assert!(matches!(Header::parse(&bytes), Err(Error::UnsupportedVersion(v)) if v == version));
+        }
+    }
+
+    #[test]
+    fn empty_header_parses() {
+        let header = Header::parse(&empty(4, 2, 0x0102)).unwrap();
+        assert!(header.is_empty());
+        assert_eq!(header.slot_count(), 4);
+        assert_eq!(header.codec, 2);
+        assert_eq!(header.payload_version, 0x0102);
+        assert_eq!(Header::parse(&empty(0, 0, 0)).unwrap().slot_count(), 1);
+    }
+
+    #[test]
+    fn newer_generations_wrap() {
+        let at = |generation| Header { generation, ..header() };
+        assert!(at(1).is_newer(&at(0)));
+        assert!(!at(0).is_newer(&at(1)));
+        assert!(!at(5).is_newer(&at(5)));
+        assert!(at(0).is_newer(&at(u16::MAX)));
+    }
+
+    #[test]
+    fn seeded_matches_runtime_header() {
+        let payload = b"seeded payload";
+        let seeded = Header::parse(&seeded(2, 1, 3, payload)).unwrap();
+        let runtime = Header {
+            payload_version: 3,
+            slots: 2,
+            ..Header::new(payload, Checksum::Crc32c, 1, 0)
+        };
+        assert_eq!(seeded, runtime);
+    }
+}
@@ -1,17 +1,85 @@
-#![allow(incomplete_features)]
-#![feature(const_fn)]
-#![feature(const_generics)]
-
 //! `binary_enclave` allows storing configuration data in a binary directly. You
 //! will probably never find a good reason for doing this. This is primarily an
 //! exercise for learning rust and something I found interesting.
 //!
-//! > _nightly warning_
-//! > this currently requires nightly for `const_fn` and `const_generics`
+//! Requires Rust 1.61 or newer, for trait bounds on `const fn`.
 //!
 //! ### Caveats
 //!
-//! * Written payload is only visible upon next execution.
+//! * Enclaves declared in a shared library live in, and are written to, that
+//!   library rather than the executable loading it. This is located with
+//!   `dladdr` and only supported on unix.
+//! * Written payload is only visible to `decode` upon next execution.
+//!   `decode` always reads the copy loaded into memory at startup, while
+//!   `decode_from_disk` reads the binary on disk, which is what the next
+//!   execution will see and is authoritative after a `write`.
+//!
+//! ### Initial Payload
+//!
+//! `#[enclave(appconfig, init = "config/default.bin")]` bakes the file, taken
+//! relative to the crate root, into the enclave at compile time. Nothing is
+//! serialized during the build, the file must already be in the enclave's
+//! codec. With `codec = "json"` a plain JSON file will do, with the default
+//! bincode it has to be produced with `bincode::serialize` beforehand, say
+//! from a test or a small tool. A `.json`, `.cbor` or `.msgpack` file given
+//! to an enclave of another codec, a file too large for the first slot and a
+//! seed for an enclave requiring signatures all fail to compile.
+//!
+//! ### Payload Size
+//!
+//! Payload types with a bounded encoding can `#[derive(MaxSize)]`, after
+//! which `#[enclave(appconfig, max_size)]` fails to compile if the enclave
+//! cannot hold the largest payload, and declaring it `Enclave<Config, _>`
+//! sizes it to fit. Sizes are only known for the bincode codec, and leave
+//! no room for the nonce and tag of `write_encrypted`.
+//!
+//! ### Format
+//!
+//! Every enclave starts with a fixed little-endian [`header`] carrying
+//! magic bytes, a format version and the payload checksum, so enclaves can be
+//! recognised by external tools whatever platform they were built for. The
+//! [`Checksum`] algorithm defaults to CRC32C and is chosen per enclave with
+//! `#[enclave(appconfig, checksum = "sha256")]`.
+//!
+//! Payloads are serialized with bincode unless another [`codec`] is chosen
+//! with `#[enclave(appconfig, codec = "json")]`, the codec being recorded in
+//! the header as well, along with a fingerprint of the payload type. Payloads
+//! written for another layout of the type fail with `Error::SchemaMismatch`
+//! rather than decoding into garbage. When the type does change, bump its
+//! `#[enclave(appconfig, version = 1)]` and register [`Migrations`] to upgrade
+//! old payloads with `decode_migrated`.
+//!
+//! ### Slots
+//!
+//! `#[enclave(appconfig, slots = 2)]` splits the enclave into A/B slots, each
+//! holding half of its size. Writes always fill the slot not in use and reads
+//! take the newest slot that validates, so a write interrupted by power loss
+//! falls back to the previous payload. Combine with `write_in_place_to` when
+//! patching binaries in place.
+//!
+//! ### Multiple Enclaves
+//!
+//! Each `#[enclave]` static gets its own section, so a program may hold
+//! several enclaves of the same payload type, say `defaults` and
+//! `overrides` of one `Config`.
+//!
+//! ### Transactions
+//!
+//! Programs with several enclaves can stage payloads for all of them in a
+//! [`Transaction`], committing them with a single rewrite of the binary.
+//!
+//! ### Encryption
+//!
+//! With the `encryption` feature, `write_encrypted` seals the payload with
+//! ChaCha20-Poly1305 under a caller supplied `Key` and `decode_encrypted`
+//! authenticates and decrypts it again.
+//!
+//! ### Signing
+//!
+//! Enclaves declared with `#[enclave(appconfig, public_key = "<hex>")]` only
+//! decode when signed by the matching Ed25519 key. Verifying needs the
+//! `signing` feature, so such enclaves fail to build without it. Provisioning
+//! tools built with it sign payloads with `write_signed_to`.
 //!
 //! ### Basic Usage
 //!
@@ -20,13 +88,13 @@
 //! use binary_enclave::{enclave, Enclave};
 //!
 //! #[derive(Default, Serialize, Deserialize)]
-//! struct Config { some: u32, values: String };
+//! struct Config { some: u32, values: String }
 //!
 //! #[enclave(appconfig)]
-//! pub static CONFIG: Enclave<Config, 128> = Enclave::new();
+//! static CONFIG: Enclave<Config, 128> = Enclave::new();
 //!
 //! fn main() -> Result<(), Box<dyn std::error::Error>> {
-//!     let conf = CONFIG.decode()?;
+//!     let conf = CONFIG.decode_or_default();
 //!     let res = CONFIG.write(&Config{ some: 43, values: "see".to_string() })?;
 //!     Ok(())
 //! }
@@ -34,20 +102,97 @@
 
 #[doc(hidden)]
 mod error;
+mod backup;
+mod checksum;
+pub mod codec;
+#[cfg(feature = "encryption")]
+mod crypto;
+pub mod header;
+mod lock;
+#[doc(hidden)]
+pub mod max_size;
+mod migrate;
+mod object;
+pub mod raw;
+#[doc(hidden)]
+pub mod schema;
+mod signature;
+mod slots;
+mod transaction;
 
 use serde::{de::DeserializeOwned, Serialize};
-use std::collections::hash_map::DefaultHasher;
 use std::fs;
-use std::hash::Hasher;
 use std::io::{Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
 
+use crate::header::{Header, HEADER_LEN};
+use crate::object::Location;
+pub use crate::checksum::Checksum;
+pub use crate::codec::EnclaveCodec;
+#[cfg(feature = "encryption")]
+pub use crate::crypto::Key;
+#[cfg(feature = "signing")]
+pub use ed25519_dalek::Keypair;
 pub use crate::error::{Error, Result};
-pub use binary_enclave_macro::enclave;
+pub use crate::max_size::MaxSize;
+pub use crate::migrate::Migrations;
+pub use crate::transaction::Transaction;
+pub use binary_enclave_macro::{enclave, MaxSize};
 
+/// Everything about an enclave decided by `#[enclave]`. It is implemented
+/// for a marker type generated per static, so several enclaves can hold
+/// the same payload type.
 #[doc(hidden)]
 pub trait EnclaveLocator {
     const SECTION: &'static str;
+    const CHECKSUM: Checksum = Checksum::Crc32c;
+    const PUBLIC_KEY: Option<[u8; 32]> = None;
+    const VERSION: u16 = 0;
+    const BACKUPS: usize = 0;
+    const SLOTS: u8 = 1;
+    type Codec: EnclaveCodec;
+
+    fn schema() -> u64 {
+        0
+    }
+}
+
+// referenced by `#[enclave]` for enclaves with a `public_key`, failing
+// the build when their signatures could never be verified.
+#[doc(hidden)]
+#[cfg(feature = "signing")]
+#[macro_export]
+macro_rules! __require_signing {
+    () => {};
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "signing"))]
+#[macro_export]
+macro_rules! __require_signing {
+    () => {
+        compile_error!("enclaves with a `public_key` need the `signing` feature of binary_enclave");
+    };
+}
+
+/// Payload type and size of an `Enclave` type, however it is named.
+#[doc(hidden)]
+#[cfg_attr(
+    diagnostic_namespace,
+    diagnostic::on_unimplemented(
+        message = "enclave must be of type Enclave<T, SIZE>, not `{Self}`",
+        label = "not an `Enclave`"
+    )
+)]
+pub trait EnclaveType {
+    type Payload;
+    const SIZE: usize;
+}
+
+impl<T, L, const SIZE: usize> EnclaveType for Enclave<T, SIZE, L> {
+    type Payload = T;
+    const SIZE: usize = SIZE;
 }
 
 /// Our enclave that will store the serialized value within our binary
@@ -59,43 +204,137 @@ pub trait EnclaveLocator {
 /// The size given will increase the size of the binary linearly.
 /// Setting this to an extremely large size will give you an extremely
 /// large binary.
+///
+/// `L` is filled in by `#[enclave]` with the marker type it generates for
+/// the static, and is not meant to be given by hand.
 #[repr(C)]
-pub struct Enclave<T, const SIZE: usize> {
-    len: usize,
-    checksum: u64,
+pub struct Enclave<T, const SIZE: usize, L = T> {
+    header: [u8; HEADER_LEN],
     pack: [u8; SIZE],
-    _phantom: PhantomData<T>,
+    _phantom: PhantomData<(T, L)>,
 }
 
-impl<T, const SIZE: usize> Enclave<T, SIZE>
+impl<T, L, const SIZE: usize> Enclave<T, SIZE, L>
 where
-    T: Default + Serialize + DeserializeOwned + EnclaveLocator,
+    T: Default + Serialize + DeserializeOwned,
+    L: EnclaveLocator,
 {
     /// Gives us a new Enclave with the size specified.
     pub const fn new() -> Self {
+        let header = header::empty(L::SLOTS, L::Codec::ID, L::VERSION);
         Self {
-            len: 0,
-            checksum: 0,
-            pack: [0; SIZE],
-            _phantom: PhantomData
+            header,
+            pack: slots::stamp(L::SLOTS, header),
+            _phantom: PhantomData,
         }
     }
 
+    /// Gives us an Enclave holding `payload` from the start, already
+    /// serialized with the enclave's codec. This is what
+    /// `#[enclave(name, init = "...")]` uses, a payload larger than the
+    /// first slot fails to compile.
+    #[doc(hidden)]
+    pub const fn seeded(payload: &[u8]) -> Self {
+        let slots = if L::SLOTS == 0 { 1 } else { L::SLOTS as usize };
+        let capacity = (HEADER_LEN + SIZE) / slots - HEADER_LEN;
+        assert!(payload.len() <= capacity, "initial payload exceeds the enclave's first slot");
+
+        let mut pack = slots::stamp(L::SLOTS, header::empty(L::SLOTS, L::Codec::ID, L::VERSION));
+        let mut idx = 0;
+        while idx < payload.len() {
+            pack[idx] = payload[idx];
+            idx += 1;
+        }
+
+        Self {
+            header: header::seeded(L::SLOTS, L::Codec::ID, L::VERSION, payload),
+            pack,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Path of the binary we were loaded from. This is the shared library
+    /// declaring us when there is one, the running executable otherwise.
+    fn binary(&self) -> Result<PathBuf> {
+        binary_of(self as *const Self as *const u8)
+    }
+
+    /// Our section as loaded into memory, header and pack together.
+    fn loaded(&self) -> Vec<u8> {
+        let mut section = self.header.to_vec();
+        section.extend_from_slice(&self.pack);
+        section
+    }
+
     /// Deserialize the embedded Enclave into an instance of our specified type.
+    ///
+    /// Enclaves declared with a `public_key` must carry a valid signature,
+    /// failing with `Error::SignatureInvalid` otherwise.
     pub fn decode(&self) -> Result<T> {
-        let payload: Result<T> = bincode::deserialize(&self.pack).map_err(From::from);
-        match payload {
-            Err(e) => Err(e),
-            Ok(payload) => {
-                let mut hasher = DefaultHasher::new();
-                hasher.write(&self.pack[0..self.len as usize]);
-                if hasher.finish() == self.checksum {
-                    Ok(payload)
-                } else {
-                    Err(Error::PayloadChecksum)
-                }
-            }
+        let section = self.loaded();
+        let slot = slots::active(&section, L::SLOTS.into())?;
+        let header = slot.header;
+        let payload = open::<L>(&header, slot.pack)?;
+        if header.is_encrypted() {
+            return Err(Error::PayloadEncrypted);
         }
+        check_current::<L>(&header)?;
+        L::Codec::decode(payload)
+    }
+
+    /// Deserialize the embedded Enclave, upgrading a payload written for an
+    /// older version of our type through `migrations`.
+    pub fn decode_migrated(&self, migrations: &Migrations<T, L>) -> Result<T> {
+        let section = self.loaded();
+        let slot = slots::active(&section, L::SLOTS.into())?;
+        let header = slot.header;
+        let payload = open::<L>(&header, slot.pack)?;
+        if header.is_encrypted() {
+            return Err(Error::PayloadEncrypted);
+        }
+        if header.payload_version == L::VERSION {
+            check_current::<L>(&header)?;
+            return L::Codec::decode(payload);
+        }
+        if header.payload_version > L::VERSION {
+            return Err(Error::PayloadVersion(header.payload_version));
+        }
+        L::Codec::decode(&migrations.upgrade(header.payload_version, payload)?)
+    }
+
+    /// Same as `decode_migrated`, but also writes the upgraded payload back
+    /// into the binary if a migration took place.
+    pub fn migrate(&self, migrations: &Migrations<T, L>) -> Result<T> {
+        let payload = self.decode_migrated(migrations)?;
+        let stored = slots::active(&self.loaded(), L::SLOTS.into())?.header;
+        if stored.payload_version != L::VERSION {
+            self.write(&payload)?;
+        }
+        Ok(payload)
+    }
+
+    /// Authenticate and decrypt the embedded Enclave with `key`, then
+    /// deserialize it. Fails with `Error::PayloadAuthentication` if the key
+    /// is wrong, the payload was tampered with or was never encrypted.
+    #[cfg(feature = "encryption")]
+    pub fn decode_encrypted(&self, key: &Key) -> Result<T> {
+        let section = self.loaded();
+        let slot = slots::active(&section, L::SLOTS.into())?;
+        let header = slot.header;
+        let payload = open::<L>(&header, slot.pack)?;
+        if !header.is_encrypted() {
+            return Err(Error::PayloadAuthentication);
+        }
+        check_current::<L>(&header)?;
+        let payload = crypto::open(key, L::SECTION, payload)?;
+        L::Codec::decode(&payload)
+    }
+
+    /// Deserialize the Enclave as currently stored in our binary on disk,
+    /// rather than the copy loaded into memory when we started. This picks
+    /// up payloads written since, such as by our own `write`.
+    pub fn decode_from_disk(&self) -> Result<T> {
+        self.read_from(self.binary()?)
     }
 
     /// Deserialize the embedded Enclave or give a default instance
@@ -109,92 +348,407 @@ where
     /// is required due to restrictions on some OS of modifying
     /// a binary currently being executing.
     pub fn write(&self, payload: &T) -> Result<usize> {
-        self._write(payload)
+        let bin_path = self.binary()?;
+        self.write_to(bin_path, payload)
     }
 
-    #[cfg(target_os = "macos")]
-    #[doc(hidden)]
-    pub fn _write(&self, payload: &T) -> Result<usize> {
-        use goblin::mach;
-
-        let mut data = read_binary()?;
-        let mach = mach::MachO::parse(&data, 0)?;
-        let segment = mach
-            .segments
-            .iter()
-            .find(|s| s.name().unwrap() == "__DATA")
-            .ok_or_else(|| Error::SectionNotFound("__DATA Segment not found".into()))?;
-        let (offset, size) = segment
-            .sections()?
-            .iter()
-            .find(|sec| sec.0.name().unwrap() == T::SECTION)
-            .map(|x| (x.0.offset, x.0.size))
-            .ok_or_else(|| Error::SectionNotFound("Binary Section not found".into()))?;
-
-        write_binary(&mut data, &payload, offset as usize, size as usize)
-    }
-
-    #[cfg(target_os = "linux")]
-    #[doc(hidden)]
-    pub fn _write(&self, payload: &T) -> Result<usize> {
-        use goblin::elf::Elf;
+    /// Write a new payload into the binary and restart into the updated
+    /// binary, with the same arguments and environment we were started
+    /// with. `shutdown` runs between the write and the restart, to flush
+    /// or release anything that would not survive the `execve`.
+    ///
+    /// This only returns if the write or the restart failed.
+    #[cfg(unix)]
+    pub fn write_and_reexec<F: FnOnce()>(&self, payload: &T, shutdown: F) -> Error {
+        if let Err(e) = self.write(payload) {
+            return e;
+        }
+        let path = match current_binary() {
+            Ok(path) => path,
+            Err(e) => return e,
+        };
+
+        shutdown();
+        reexec(&path)
+    }
+
+    /// Write a new payload into the binary at `path` rather than the
+    /// currently running executable. The binary must contain this
+    /// enclave's section, typically being another build of this program.
+    pub fn write_to<P: AsRef<Path>>(&self, path: P, payload: &T) -> Result<usize> {
+        let payload = L::Codec::encode(payload)?;
+        let header = header_for::<L>(&payload, 0);
+        update(path.as_ref(), L::SECTION, L::BACKUPS, Commit::Replace, |_| {
+            Ok((header, payload))
+        })
+    }
+
+    /// Write a new payload into the binary, unless the enclave on disk has
+    /// changed since we read it. `expected` is the checksum it was read
+    /// with, such as from `checksum`, failing with `Error::WriteConflict`
+    /// if another writer got there first.
+    pub fn write_if_unchanged(&self, expected: &[u8; 32], payload: &T) -> Result<usize> {
+        let payload = L::Codec::encode(payload)?;
+        let header = header_for::<L>(&payload, 0);
+        update(&self.binary()?, L::SECTION, L::BACKUPS, Commit::Replace, |data| {
+            if raw::section(data, L::SECTION)?.header.checksum != *expected {
+                return Err(Error::WriteConflict);
+            }
+            Ok((header, payload))
+        })
+    }
+
+    /// Checksum of the payload loaded into memory, as recorded in its
+    /// header. Zero for an enclave that was never written.
+    pub fn checksum(&self) -> [u8; 32] {
+        slots::active(&self.loaded(), L::SLOTS.into())
+            .map(|slot| slot.header.checksum)
+            .unwrap_or_default()
+    }
+
+    /// Write a new payload into the binary, encrypted with `key`. It can
+    /// only be read back with `decode_encrypted` and the same key.
+    #[cfg(feature = "encryption")]
+    pub fn write_encrypted(&self, payload: &T, key: &Key) -> Result<usize> {
+        let payload = crypto::seal(key, L::SECTION, &L::Codec::encode(payload)?)?;
+        let header = header_for::<L>(&payload, header::FLAG_ENCRYPTED);
+        update(&self.binary()?, L::SECTION, L::BACKUPS, Commit::Replace, |_| {
+            Ok((header, payload))
+        })
+    }
+
+    /// Write a new payload into the binary at `path`, signed with `keypair`.
+    /// This is meant for provisioning tools, the program itself only holds
+    /// the public key given to `#[enclave(name, public_key = "...")]`.
+    #[cfg(feature = "signing")]
+    pub fn write_signed_to<P: AsRef<Path>>(
+        &self,
+        path: P,
+        payload: &T,
+        keypair: &Keypair,
+    ) -> Result<usize> {
+        let payload = signature::sign(keypair, L::SECTION, &L::Codec::encode(payload)?);
+        let header = header_for::<L>(&payload, header::FLAG_SIGNED);
+        update(path.as_ref(), L::SECTION, L::BACKUPS, Commit::Replace, |_| {
+            Ok((header, payload))
+        })
+    }
 
-        let mut data = read_binary()?;
-        let elf = Elf::parse(&data)?;
-        let section = elf
-            .section_headers
-            .iter()
-            .find(|sec| &elf.shdr_strtab[sec.sh_name] == T::SECTION)
-            .ok_or_else(|| Error::SectionNotFound("Binary Section not found".into()))?;
+    /// Restore the payload replaced by the last write, as kept when the
+    /// enclave is declared with `#[enclave(name, backups = 5)]`. Fails
+    /// with `Error::NoBackup` once the history is exhausted.
+    pub fn rollback(&self) -> Result<usize> {
+        raw::rollback(self.binary()?, L::SECTION)
+    }
 
-        write_binary(&mut data, &payload, section.sh_offset as usize, section.sh_size as usize)
+    /// Write a new payload directly into the binary at `path`, rather than
+    /// replacing the binary with a patched copy. This suits storage where
+    /// rewriting the whole binary is undesirable, but cannot be used on a
+    /// running binary. Declare the enclave with `slots = 2` so a torn write
+    /// leaves the previous payload to fall back on.
+    pub fn write_in_place_to<P: AsRef<Path>>(&self, path: P, payload: &T) -> Result<usize> {
+        let payload = L::Codec::encode(payload)?;
+        let header = header_for::<L>(&payload, 0);
+        update(path.as_ref(), L::SECTION, L::BACKUPS, Commit::InPlace, |_| {
+            Ok((header, payload))
+        })
     }
 
-    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
-    pub fn _write(&self, payload: &T) -> Result<usize> {
-        panic!("Not Supported")
+    /// Deserialize this enclave's payload as found in the binary at `path`.
+    /// Unlike `decode` this reads the file on disk, not the loaded static.
+    pub fn read_from<P: AsRef<Path>>(&self, path: P) -> Result<T> {
+        let data = read_binary(path.as_ref())?;
+        let section = raw::section(&data, L::SECTION)?;
+        let payload = open::<L>(&section.header, &section.pack)?;
+        if section.header.is_encrypted() {
+            return Err(Error::PayloadEncrypted);
+        }
+        check_current::<L>(&section.header)?;
+        L::Codec::decode(payload)
     }
 }
 
-fn read_binary() -> Result<Vec<u8>> {
-    let bin_path = std::env::current_exe()?;
-    let bytes = fs::read(bin_path)?;
-    Ok(bytes)
+impl<T, L, const SIZE: usize> Default for Enclave<T, SIZE, L>
+where
+    T: Default + Serialize + DeserializeOwned,
+    L: EnclaveLocator,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Verify the checksum, signature and codec of `pack`, giving back the payload.
+fn open<'a, L: EnclaveLocator>(header: &Header, pack: &'a [u8]) -> Result<&'a [u8]> {
+    let payload = raw::verify(header, pack)?;
+    let payload = signature::check(L::PUBLIC_KEY.as_ref(), L::SECTION, header, payload)?;
+    if header.codec != L::Codec::ID {
+        return Err(Error::CodecMismatch(header.codec));
+    }
+    Ok(payload)
 }
 
-fn write_binary<T: Serialize>(
-    data: &mut Vec<u8>,
-    payload: &T,
-    offset: usize,
-    size: usize,
-) -> Result<usize> {
-    let payload = bincode::serialize(payload)?;
-    if payload.len() > size {
-        return Err(Error::SectionSizeExceeded {
-            payload: payload.len(),
-            section: size,
+/// Check the payload was written for the current version and layout of the
+/// payload type of `L`.
+fn check_current<L: EnclaveLocator>(header: &Header) -> Result<()> {
+    if header.payload_version != L::VERSION {
+        return Err(Error::PayloadVersion(header.payload_version));
+    }
+
+    let expected = L::schema();
+    if header.schema != 0 && expected != 0 && header.schema != expected {
+        return Err(Error::SchemaMismatch {
+            expected,
+            found: header.schema,
         });
     }
+    Ok(())
+}
 
-    let mut hasher = DefaultHasher::new();
-    hasher.write(&payload);
+/// Header for a payload of the enclave `L`, as stored by this build.
+fn header_for<L: EnclaveLocator>(payload: &[u8], flags: u16) -> Header {
+    Header {
+        payload_version: L::VERSION,
+        schema: L::schema(),
+        ..Header::new(payload, L::CHECKSUM, L::Codec::ID, flags)
+    }
+}
 
-    let mut data = std::io::Cursor::new(data);
-    data.seek(SeekFrom::Start(offset as u64))?;
-    data.write_all(&payload.len().to_ne_bytes())?;
-    data.write_all(&hasher.finish().to_ne_bytes())?;
-    data.write_all(&payload)?;
-    let data = data.into_inner();
-
-    let file = std::env::current_exe()?;
-    let perms = fs::metadata(&file)?.permissions();
-    let file_name = file.file_name().ok_or_else(|| Error::BinaryNotLocated)?;
-    let mut tmpfile = Clone::clone(&file);
-    tmpfile.set_file_name(format!("{}.new", &file_name.to_string_lossy()));
-
-    fs::write(&tmpfile, &data)?;
-    fs::rename(&tmpfile, &file)?;
-    fs::set_permissions(&file, perms)?;
+/// Path of the running executable. Once replaced by a `write`, Linux
+/// reports it with a ` (deleted)` suffix, which we drop again to get the
+/// path of the replacement.
+fn current_binary() -> Result<PathBuf> {
+    let path = std::env::current_exe()?;
+    if path.exists() {
+        return Ok(path);
+    }
+
+    let name = path.to_string_lossy();
+    match name.strip_suffix(" (deleted)") {
+        Some(name) => Ok(PathBuf::from(name)),
+        None => Err(Error::BinaryNotLocated),
+    }
+}
+
+/// Path of the binary `addr` was loaded from. Statics of a shared library
+/// resolve to the library, as reported by `dladdr`, anything else to the
+/// running executable.
+#[cfg(unix)]
+fn binary_of(addr: *const u8) -> Result<PathBuf> {
+    use std::ffi::{CStr, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut info: libc::Dl_info = unsafe { std::mem::zeroed() };
+    let found = unsafe { libc::dladdr(addr as *const libc::c_void, &mut info) } != 0;
+    let exe = current_binary()?;
+    if !found || info.dli_fname.is_null() {
+        return Ok(exe);
+    }
+
+    // the executable itself is reported by the name it was started with,
+    // only absolute names of other objects are trusted.
+    let name = unsafe { CStr::from_ptr(info.dli_fname) };
+    let path = PathBuf::from(OsStr::from_bytes(name.to_bytes()));
+    if !path.is_absolute() || !path.exists() {
+        return Ok(exe);
+    }
+    match (path.canonicalize(), exe.canonicalize()) {
+        (Ok(lib), Ok(exe)) if lib != exe => Ok(path),
+        _ => Ok(exe),
+    }
+}
+
+// locating the module holding a static is not implemented outside of
+// unix, enclaves are always taken to live in the executable.
+#[cfg(not(unix))]
+fn binary_of(_addr: *const u8) -> Result<PathBuf> {
+    current_binary()
+}
+
+/// Replace the running process with `path`, keeping our arguments and
+/// environment.
+#[cfg(unix)]
+fn reexec(path: &Path) -> Error {
+    use std::os::unix::process::CommandExt;
+
+    let mut args = std::env::args_os();
+    let mut cmd = std::process::Command::new(path);
+    if let Some(arg0) = args.next() {
+        cmd.arg0(arg0);
+    }
+    Error::File(cmd.args(args).exec())
+}
+
+fn read_binary(path: &Path) -> Result<Vec<u8>> {
+    let bytes = fs::read(path)?;
+    Ok(bytes)
+}
+
+/// How a patched binary is committed to disk.
+#[derive(Clone, Copy)]
+enum Commit {
+    /// Replace the binary with a patched copy.
+    Replace,
+    /// Write the patched slot into the binary itself.
+    InPlace,
+}
 
+/// Patch `section` of the binary at `path` with whatever `build` gives
+/// for its current contents, holding the binary's lock from read to rename.
+/// The payload being replaced is kept in a history of up to `keep` entries.
+fn update<F>(path: &Path, section: &str, keep: usize, commit: Commit, build: F) -> Result<usize>
+where
+    F: FnOnce(&[u8]) -> Result<(Header, Vec<u8>)>,
+{
+    let _lock = lock::FileLock::acquire(path)?;
+    let mut data = read_binary(path)?;
+    let (header, payload) = build(&data)?;
+    let patched = patch(&mut data, section, keep, header, &payload)?;
+    write_binary(path, &data, &patched.targets, commit)?;
+    patched.keep_backup(path, keep)?;
     Ok(payload.len())
 }
+
+/// A section patched in memory, waiting for the binary to be committed.
+struct Patched {
+    /// Locations within the binary that changed.
+    targets: Vec<Location>,
+    /// The section as it was before, if its payload is to be kept.
+    replaced: Option<raw::Section>,
+}
+
+impl Patched {
+    /// Keep the replaced payload in the history of the binary at `path`.
+    /// Only done once the binary is committed, so a failed write neither
+    /// adds to the history nor evicts from it.
+    fn keep_backup(&self, path: &Path, keep: usize) -> Result<()> {
+        match &self.replaced {
+            Some(replaced) => backup::save(path, replaced, keep),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Patch `section` within `data` with `payload`, noting the payload being
+/// replaced if up to `keep` of them are kept in the history.
+fn patch(
+    data: &mut [u8],
+    section: &str,
+    keep: usize,
+    header: Header,
+    payload: &[u8],
+) -> Result<Patched> {
+    let locations = object::locate(data, section)?;
+
+    // every copy of the section is kept in step, so the first decides
+    // which slot is written next.
+    let first = &locations[0];
+    let current = data
+        .get(first.offset..first.offset + first.size)
+        .ok_or_else(|| Error::SectionNotFound("Binary Section truncated".into()))?;
+    let (index, count, generation) = slots::next(current, slots::count(current)?)?;
+    let header = Header {
+        slots: count as u8,
+        generation,
+        ..header
+    };
+
+    let targets: Vec<Location> = locations
+        .iter()
+        .map(|location| slots::locate(location, count, index))
+        .collect();
+    for target in &targets {
+        let size = target.size.saturating_sub(HEADER_LEN);
+        if payload.len() > size {
+            return Err(Error::SectionSizeExceeded {
+                payload: payload.len(),
+                section: size,
+            });
+        }
+    }
+
+    let replaced = match keep {
+        0 => None,
+        _ => Some(raw::section(data, section)?).filter(|current| !current.header.is_empty()),
+    };
+
+    let mut data = std::io::Cursor::new(data);
+    for target in &targets {
+        data.seek(SeekFrom::Start(target.offset as u64))?;
+        data.write_all(&header.to_bytes())?;
+        data.write_all(payload)?;
+    }
+    Ok(Patched { targets, replaced })
+}
+
+/// Commit the patched `data` of the binary at `file`, where `patched` are
+/// the locations that changed.
+fn write_binary(file: &Path, data: &[u8], patched: &[Location], commit: Commit) -> Result<()> {
+    match commit {
+        Commit::Replace => replace_file(file, data),
+        Commit::InPlace => {
+            let mut file = fs::OpenOptions::new().write(true).open(file)?;
+            for location in patched {
+                file.seek(SeekFrom::Start(location.offset as u64))?;
+                file.write_all(&data[location.offset..location.offset + location.size])?;
+            }
+            Ok(file.sync_all()?)
+        }
+    }
+}
+
+/// Durably replace `file` with `data`. The data is written to a uniquely
+/// named temp file beside it, synced, and renamed over `file`, syncing the
+/// directory on both sides of the rename. A crash leaves either the old or
+/// the new binary in place, and a failed write removes its temp file.
+fn replace_file(file: &Path, data: &[u8]) -> Result<()> {
+    let perms = fs::metadata(file)?.permissions();
+    let file_name = file.file_name().ok_or(Error::BinaryNotLocated)?;
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+
+    let (tmpfile, mut tmp) = create_temp(dir, &file_name.to_string_lossy())?;
+    let res = (|| {
+        tmp.write_all(data)?;
+        tmp.set_permissions(perms)?;
+        tmp.sync_all()?;
+        sync_dir(dir)?;
+        fs::rename(&tmpfile, file)?;
+        sync_dir(dir)
+    })();
+
+    if res.is_err() {
+        let _ = fs::remove_file(&tmpfile);
+    }
+    Ok(res?)
+}
+
+/// Exclusively create a temp file in `dir` no other writer can be using.
+fn create_temp(dir: &Path, name: &str) -> Result<(PathBuf, fs::File)> {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    loop {
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = dir.join(format!(".{}.{}.{}.tmp", name, std::process::id(), count));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> std::io::Result<()> {
+    fs::File::open(dir)?.sync_all()
+}
+
+// directories cannot be opened for syncing on windows, the rename
+// itself is as durable as it gets there.
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> std::io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Advisory locking of a binary across processes.
+//!
+//! The binary itself is replaced by every write, so a lock on it would
+//! only guard a file that is about to disappear. Writers instead lock a
+//! `.<name>.lock` file kept beside it for as long as the write takes.
+
+use crate::error::{Error, Result};
+use std::fs::{File, OpenOptions};
+use std::path::Path;
+
+/// An exclusive lock on a binary, released on drop.
+pub(crate) struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Block until we hold the lock of the binary at `path`.
+    pub(crate) fn acquire(path: &Path) -> Result<Self> {
+        let name = path.file_name().ok_or(Error::BinaryNotLocated)?;
+        let lock_path = path.with_file_name(format!(".{}.lock", name.to_string_lossy()));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(lock_path)?;
+
+        lock(&file)?;
+        Ok(Self { _file: file })
+    }
+}
+
+#[cfg(unix)]
+fn lock(file: &File) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+}
+
+// no advisory locking outside of unix, writers are only kept from
+// clobbering each others temp files.
+#[cfg(not(unix))]
+fn lock(_file: &File) -> Result<()> {
+    Ok(())
+}
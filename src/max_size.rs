@@ -0,0 +1,135 @@
+//! Worst-case encoded size of payload types.
+//!
+//! A payload type with a bounded encoding can have its enclave checked at
+//! compile time with `#[enclave(name, max_size)]`, or sized automatically
+//! by declaring it `Enclave<T, _>`. Sizes are those of the bincode codec,
+//! the only one the check is available for.
+
+use crate::header::HEADER_LEN;
+use crate::signature::SIGNATURE_LEN;
+use std::marker::PhantomData;
+
+/// Types whose bincode encoding never exceeds `MAX_SIZE` bytes.
+///
+/// Derive it with `#[derive(MaxSize)]` for structs and enums whose fields
+/// all implement it. Anything holding a `String`, `Vec` or other unbounded
+/// collection has no maximum and cannot implement it.
+pub trait MaxSize {
+    /// Largest number of bytes a value serializes to.
+    const MAX_SIZE: usize;
+}
+
+/// Largest of `sizes`, for the variants of a derived enum.
+pub const fn largest(sizes: &[usize]) -> usize {
+    let mut largest = 0;
+    let mut idx = 0;
+    while idx < sizes.len() {
+        if sizes[idx] > largest {
+            largest = sizes[idx];
+        }
+        idx += 1;
+    }
+    largest
+}
+
+/// `SIZE` an enclave split into `slots` needs to hold a payload of up to
+/// `payload` bytes in every slot, along with a signature if `signed`.
+pub const fn required(slots: u8, payload: usize, signed: bool) -> usize {
+    let slots = if slots == 0 { 1 } else { slots as usize };
+    let payload = if signed {
+        payload + SIGNATURE_LEN
+    } else {
+        payload
+    };
+    slots * (HEADER_LEN + payload) - HEADER_LEN
+}
+
+macro_rules! max_size {
+    ($($ty:ty => $size:expr),* $(,)?) => {
+        $(impl MaxSize for $ty {
+            const MAX_SIZE: usize = $size;
+        })*
+    };
+}
+
+// bincode writes integers at their full width, sizes as u64, chars as
+// UTF-8 and enum variants as a u32 index.
+max_size! {
+    () => 0,
+    bool => 1,
+    u8 => 1,
+    i8 => 1,
+    u16 => 2,
+    i16 => 2,
+    u32 => 4,
+    i32 => 4,
+    f32 => 4,
+    char => 4,
+    u64 => 8,
+    i64 => 8,
+    f64 => 8,
+    usize => 8,
+    isize => 8,
+    u128 => 16,
+    i128 => 16,
+}
+
+impl<T> MaxSize for PhantomData<T> {
+    const MAX_SIZE: usize = 0;
+}
+
+impl<T: MaxSize> MaxSize for Option<T> {
+    const MAX_SIZE: usize = 1 + T::MAX_SIZE;
+}
+
+impl<T: MaxSize> MaxSize for Box<T> {
+    const MAX_SIZE: usize = T::MAX_SIZE;
+}
+
+impl<T: MaxSize, const N: usize> MaxSize for [T; N] {
+    const MAX_SIZE: usize = N * T::MAX_SIZE;
+}
+
+macro_rules! tuple_max_size {
+    ($($name:ident)+) => {
+        impl<$($name: MaxSize),+> MaxSize for ($($name,)+) {
+            const MAX_SIZE: usize = 0 $(+ $name::MAX_SIZE)+;
+        }
+    };
+}
+
+tuple_max_size! { A }
+tuple_max_size! { A B }
+tuple_max_size! { A B C }
+tuple_max_size! { A B C D }
+tuple_max_size! { A B C D E }
+tuple_max_size! { A B C D E F }
+tuple_max_size! { A B C D E F G }
+tuple_max_size! { A B C D E F G H }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn required_fits_one_slot() {
+        assert_eq!(required(1, 100, false), 100);
+        assert_eq!(required(0, 100, false), 100);
+        assert_eq!(required(1, 100, true), 100 + SIGNATURE_LEN);
+    }
+
+    #[test]
+    fn required_fits_every_slot() {
+        let size = required(3, 100, false);
+        assert_eq!(size, 3 * (HEADER_LEN + 100) - HEADER_LEN);
+        assert_eq!((HEADER_LEN + size) / 3 - HEADER_LEN, 100);
+        assert_eq!(required(2, 100, true), 2 * (HEADER_LEN + 100 + SIGNATURE_LEN) - HEADER_LEN);
+    }
+
+    #[test]
+    fn composite_sizes() {
+        assert_eq!(<(u8, Option<u32>, [u16; 3])>::MAX_SIZE, 1 + 5 + 6);
+        assert_eq!(largest(&[]), 0);
+        assert_eq!(largest(&[4, 12, 8]), 12);
+    }
+}
@@ -0,0 +1,143 @@
+//! Upgrading payloads written for older versions of the payload type.
+//!
+//! Each enclave carries the payload version given to
+//! `#[enclave(name, version = 2)]`. A payload of an older version is run
+//! through one migration per version until it reaches the current one.
+
+use crate::codec::EnclaveCodec;
+use crate::error::{Error, Result};
+use crate::EnclaveLocator;
+use serde::{de::DeserializeOwned, Serialize};
+use std::marker::PhantomData;
+
+type Step = Box<dyn Fn(&[u8]) -> Result<Vec<u8>>>;
+
+/// Chain of migrations bringing old payloads up to the enclave's version.
+///
+/// ```no_run
+/// # use binary_enclave::{enclave, Enclave, Migrations};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Deserialize)]
+/// # struct ConfigV0 { port: u16 }
+/// # #[derive(Serialize, Deserialize)]
+/// # struct ConfigV1 { port: u32 }
+/// # #[derive(Default, Serialize, Deserialize)]
+/// # struct Config { port: u32, host: String }
+/// # impl From<ConfigV0> for ConfigV1 {
+/// #     fn from(old: ConfigV0) -> Self { ConfigV1 { port: old.port.into() } }
+/// # }
+/// # impl From<ConfigV1> for Config {
+/// #     fn from(old: ConfigV1) -> Self { Config { port: old.port, host: String::new() } }
+/// # }
+/// #[enclave(appconfig, version = 2)]
+/// static CONFIG: Enclave<Config, 128> = Enclave::new();
+///
+/// # fn main() -> binary_enclave::Result<()> {
+/// let migrations = Migrations::new()
+///     .step(0, |old: ConfigV0| ConfigV1::from(old))
+///     .step(1, |old: ConfigV1| Config::from(old));
+/// let conf = CONFIG.decode_migrated(&migrations)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// `L` is the locator of the enclave being migrated, which is inferred
+/// from the enclave the chain is passed to.
+pub struct Migrations<T, L = T> {
+    steps: Vec<(u16, Step)>,
+    _phantom: PhantomData<(T, L)>,
+}
+
+impl<T, L: EnclaveLocator> Migrations<T, L> {
+    /// An empty chain, only accepting payloads of the current version.
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Register the migration of a payload at version `from` into one at
+    /// version `from + 1`.
+    pub fn step<Old, New, F>(mut self, from: u16, migrate: F) -> Self
+    where
+        Old: DeserializeOwned,
+        New: Serialize,
+        F: Fn(Old) -> New + 'static,
+    {
+        let step = move |payload: &[u8]| {
+            let old: Old = L::Codec::decode(payload)?;
+            L::Codec::encode(&migrate(old))
+        };
+        self.steps.push((from, Box::new(step)));
+        self
+    }
+
+    /// Bring `payload` at `version` up to the enclave's current version.
+    pub(crate) fn upgrade(&self, mut version: u16, payload: &[u8]) -> Result<Vec<u8>> {
+        let mut payload = payload.to_vec();
+        while version < L::VERSION {
+            let (_, step) = self
+                .steps
+                .iter()
+                .find(|(from, _)| *from == version)
+                .ok_or(Error::MigrationMissing(version))?;
+            payload = step(&payload)?;
+            version += 1;
+        }
+        Ok(payload)
+    }
+}
+
+impl<T, L: EnclaveLocator> Default for Migrations<T, L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Bincode;
+
+    struct V2;
+
+    impl EnclaveLocator for V2 {
+        const SECTION: &'static str = "migrate";
+        const VERSION: u16 = 2;
+        type Codec = Bincode;
+    }
+
+    fn migrations() -> Migrations<String, V2> {
+        Migrations::new()
+            .step(0, |old: u8| old as u32 * 10)
+            .step(1, |old: u32| format!("v{}", old))
+    }
+
+    #[test]
+    fn upgrade_chains_every_step() {
+        let payload = Bincode::encode(&4u8).unwrap();
+        let upgraded = migrations().upgrade(0, &payload).unwrap();
+        assert_eq!(Bincode::decode::<String>(&upgraded).unwrap(), "v40");
+    }
+
+    #[test]
+    fn upgrade_starts_at_the_stored_version() {
+        let payload = Bincode::encode(&7u32).unwrap();
+        let upgraded = migrations().upgrade(1, &payload).unwrap();
+        assert_eq!(Bincode::decode::<String>(&upgraded).unwrap(), "v7");
+    }
+
+    #[test]
+    fn current_version_is_untouched() {
+        let payload = Bincode::encode(&"current".to_string()).unwrap();
+        assert_eq!(migrations().upgrade(2, &payload).unwrap(), payload);
+    }
+
+    #[test]
+    fn missing_step_is_reported() {
+        let migrations = Migrations::<String, V2>::new().step(1, |old: u32| format!("v{}", old));
+        let payload = Bincode::encode(&4u8).unwrap();
+        assert!(matches!(migrations.upgrade(0, &payload), Err(Error::MigrationMissing(0))));
+    }
+}
@@ -0,0 +1,237 @@
+//! Locates enclave sections within a binary, whatever format it is.
+//!
+//! The format is detected from the file itself rather than the target we
+//! were compiled for, so any build can patch ELF, Mach-O (thin or fat) and
+//! PE binaries alike.
+
+use crate::error::{Error, Result};
+use goblin::elf::{section_header::SHT_NOBITS, Elf};
+use goblin::mach::{Mach, MachO};
+use goblin::pe::PE;
+use goblin::Object;
+
+/// Where a copy of an enclave section lives within a binary file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Location {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Find every copy of the enclave section `name` within `data`. This is
+/// usually a single location, but fat Mach-O binaries carry one copy per
+/// architecture and all of them need patching.
+pub(crate) fn locate(data: &[u8], name: &str) -> Result<Vec<Location>> {
+    let locations = match Object::parse(data)? {
+        Object::Elf(elf) => elf_section(&elf, name).into_iter().collect(),
+        Object::Mach(Mach::Binary(macho)) => macho_section(&macho, name, 0)?.into_iter().collect(),
+        Object::Mach(Mach::Fat(fat)) => {
+            let mut locations = Vec::new();
+            for (idx, arch) in fat.iter_arches().enumerate() {
+                let arch = arch?;
+                let macho = fat.get(idx)?;
+                locations.extend(macho_section(&macho, name, arch.offset as usize)?);
+            }
+            locations
+        }
+        Object::PE(pe) => pe_section(&pe, name).into_iter().collect(),
+        _ => return Err(Error::UnsupportedBinary),
+    };
+
+    if locations.is_empty() {
+        return Err(Error::SectionNotFound("Binary Section not found".into()));
+    }
+    Ok(locations)
+}
+
+/// List every section within `data` that could hold an enclave, named as
+/// given to `#[enclave]`. Fat Mach-O binaries only report the first copy
+/// of each section.
+pub(crate) fn sections(data: &[u8]) -> Result<Vec<(String, Location)>> {
+    let mut sections = Vec::new();
+    match Object::parse(data)? {
+        Object::Elf(elf) => {
+            for sec in elf.section_headers.iter().filter(|sec| sec.sh_type != SHT_NOBITS) {
+                let name = elf.shdr_strtab.get(sec.sh_name).and_then(|name| name.ok());
+                let name = name.and_then(|name| name.strip_prefix('.'));
+                push_section(&mut sections, name, sec.sh_offset, sec.sh_size);
+            }
+        }
+        Object::Mach(Mach::Binary(macho)) => macho_sections(&mut sections, &macho, 0)?,
+        Object::Mach(Mach::Fat(fat)) => {
+            for (idx, arch) in fat.iter_arches().enumerate() {
+                let arch = arch?;
+                let macho = fat.get(idx)?;
+                macho_sections(&mut sections, &macho, arch.offset as usize)?;
+            }
+        }
+        Object::PE(pe) => {
+            for sec in &pe.sections {
+                let name = sec.name().ok().and_then(|name| name.strip_prefix('.'));
+                let size = sec.virtual_size.min(sec.size_of_raw_data);
+                push_section(&mut sections, name, sec.pointer_to_raw_data.into(), size.into());
+            }
+        }
+        _ => return Err(Error::UnsupportedBinary),
+    }
+    Ok(sections)
+}
+
+fn push_section(sections: &mut Vec<(String, Location)>, name: Option<&str>, offset: u64, size: u64) {
+    let name = match name {
+        Some(name) if !name.is_empty() && size > 0 => name,
+        _ => return,
+    };
+    if sections.iter().any(|(known, _)| known == name) {
+        return;
+    }
+    let location = Location {
+        offset: offset as usize,
+        size: size as usize,
+    };
+    sections.push((name.to_string(), location));
+}
+
+fn macho_sections(sections: &mut Vec<(String, Location)>, macho: &MachO, base: usize) -> Result<()> {
+    if let Some(segment) = macho.segments.iter().find(|s| s.name().ok() == Some("__DATA")) {
+        for (sec, _) in segment.sections()? {
+            let name = sec.name().ok().and_then(|name| name.strip_prefix("__"));
+            push_section(sections, name, (base + sec.offset as usize) as u64, sec.size);
+        }
+    }
+    Ok(())
+}
+
+fn elf_section(elf: &Elf, name: &str) -> Option<Location> {
+    let section = format!(".{}", name);
+    elf.section_headers
+        .iter()
+        .filter(|sec| sec.sh_type != SHT_NOBITS)
+        .find(|sec| elf.shdr_strtab[sec.sh_name] == section)
+        .map(|sec| Location {
+            offset: sec.sh_offset as usize,
+            size: sec.sh_size as usize,
+        })
+}
+
+fn macho_section(macho: &MachO, name: &str, base: usize) -> Result<Option<Location>> {
+    let section = format!("__{}", name);
+    let segment = match macho.segments.iter().find(|s| s.name().ok() == Some("__DATA")) {
+        Some(segment) => segment,
+        None => return Ok(None),
+    };
+
+    Ok(segment
+        .sections()?
+        .iter()
+        .find(|(sec, _)| sec.name().ok() == Some(section.as_str()))
+        .map(|(sec, _)| Location {
+            offset: base + sec.offset as usize,
+            size: sec.size as usize,
+        }))
+}
+
+fn pe_section(pe: &PE, name: &str) -> Option<Location> {
+    let section = format!(".{}", name);
+    pe.sections
+        .iter()
+        .find(|sec| sec.name().ok() == Some(section.as_str()))
+        .map(|sec| Location {
+            offset: sec.pointer_to_raw_data as usize,
+            // raw data is padded out to the file alignment, only the
+            // virtual size is backed by the enclave static itself.
+            size: sec.virtual_size.min(sec.size_of_raw_data) as usize,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Built by tests/fixtures/generate.py, each holds a 192 byte `appconf`.
+    const ELF: &[u8] = include_bytes!("../tests/fixtures/elf");
+    const PE: &[u8] = include_bytes!("../tests/fixtures/pe.exe");
+    const MACHO: &[u8] = include_bytes!("../tests/fixtures/macho");
+    const FAT: &[u8] = include_bytes!("../tests/fixtures/fat");
+
+    fn at(offset: usize) -> Location {
+        Location { offset, size: 192 }
+    }
+
+    #[test]
+    fn locate_elf() {
+        assert_eq!(locate(ELF, "appconf").unwrap(), vec![at(80)]);
+    }
+
+    #[test]
+    fn locate_pe_ignores_alignment_padding() {
+        assert_eq!(locate(PE, "appconf").unwrap(), vec![at(0x400)]);
+    }
+
+    #[test]
+    fn locate_macho() {
+        assert_eq!(locate(MACHO, "appconf").unwrap(), vec![at(448)]);
+    }
+
+    #[test]
+    fn locate_fat_macho_finds_every_arch() {
+        assert_eq!(locate(FAT, "appconf").unwrap(), vec![at(0x1000 + 448), at(0x2000 + 448)]);
+    }
+
+    #[test]
+    fn located_sections_hold_the_enclave() {
+        for data in [ELF, PE, MACHO, FAT] {
+            for location in locate(data, "appconf").unwrap() {
+                assert_eq!(&data[location.offset..location.offset + 4], b"ENCL");
+            }
+        }
+    }
+
+    #[test]
+    fn locate_missing_section() {
+        for data in [ELF, PE, MACHO, FAT] {
+            assert!(matches!(locate(data, "missing"), Err(Error::SectionNotFound(_))));
+        }
+    }
+
+    #[test]
+    fn locate_macho_only_searches_data_segment() {
+        assert!(matches!(locate(MACHO, "text"), Err(Error::SectionNotFound(_))));
+    }
+
+    #[test]
+    fn locate_unsupported_binary() {
+        assert!(locate(b"not a binary at all", "appconf").is_err());
+    }
+
+    fn names(data: &[u8]) -> Vec<String> {
+        sections(data).unwrap().into_iter().map(|(name, _)| name).collect()
+    }
+
+    #[test]
+    fn sections_elf_skips_nobits() {
+        assert_eq!(names(ELF), ["text", "appconf", "shstrtab"]);
+    }
+
+    #[test]
+    fn sections_pe() {
+        assert_eq!(names(PE), ["text", "appconf"]);
+        let (_, location) = &sections(PE).unwrap()[1];
+        assert_eq!(*location, at(0x400));
+    }
+
+    #[test]
+    fn sections_macho_lists_data_segment() {
+        assert_eq!(names(MACHO), ["data", "appconf"]);
+        assert_eq!(sections(MACHO).unwrap()[1].1, at(448));
+    }
+
+    #[test]
+    fn sections_fat_macho_reports_first_copy() {
+        let data = Location {
+            offset: 0x1000 + 432,
+            size: 16,
+        };
+        let expected = vec![("data".to_string(), data), ("appconf".to_string(), at(0x1000 + 448))];
+        assert_eq!(sections(FAT).unwrap(), expected);
+    }
+}
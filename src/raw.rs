@@ -0,0 +1,140 @@
+//! Untyped access to enclave sections.
+//!
+//! Everything here works on the serialized payload rather than a decoded
+//! value, for tooling that handles binaries without knowing their types.
+
+use crate::error::{Error, Result};
+use crate::backup;
+use crate::checksum::Checksum;
+use crate::header::{Header, CHECKSUM_NONE};
+use crate::object::{self, Location};
+use crate::slots;
+use crate::Commit;
+use std::path::Path;
+
+pub use crate::backup::Backup;
+
+/// An enclave section as found within a binary file.
+#[derive(Debug, Clone)]
+pub struct Section {
+    /// Section name as given to `#[enclave]`.
+    pub name: String,
+    /// Bytes available for the payload, excluding the header.
+    pub capacity: usize,
+    /// Slot the payload was read from, see `Header::slot_count`.
+    pub slot: usize,
+    /// Header preceding the payload.
+    pub header: Header,
+    /// The whole payload area, including any unused trailing bytes.
+    pub pack: Vec<u8>,
+}
+
+impl Section {
+    fn parse(name: &str, data: &[u8], location: &Location) -> Result<Self> {
+        let section = data
+            .get(location.offset..location.offset + location.size)
+            .ok_or_else(|| Error::SectionNotFound("Binary Section truncated".into()))?;
+
+        let slot = slots::active(section, slots::count(section)?)?;
+        Ok(Self {
+            name: name.to_string(),
+            capacity: slot.pack.len(),
+            slot: slot.index,
+            header: slot.header,
+            pack: slot.pack.to_vec(),
+        })
+    }
+
+    /// The stored payload, or `None` if the header claims more than fits.
+    pub fn payload(&self) -> Option<&[u8]> {
+        self.pack.get(0..self.header.len as usize)
+    }
+
+    /// The checksum algorithm in use, if known.
+    pub fn checksum(&self) -> Option<Checksum> {
+        Checksum::from_id(self.header.algorithm).ok()
+    }
+
+    /// Check the stored checksum against the stored payload.
+    pub fn verify(&self) -> Result<&[u8]> {
+        verify(&self.header, &self.pack)
+    }
+}
+
+/// Every section within `data` that starts with an enclave header.
+pub fn sections(data: &[u8]) -> Result<Vec<Section>> {
+    let sections = object::sections(data)?
+        .iter()
+        .filter_map(|(name, location)| Section::parse(name, data, location).ok())
+        .collect();
+    Ok(sections)
+}
+
+/// The enclave section `name` within `data`.
+pub fn section(data: &[u8], name: &str) -> Result<Section> {
+    let locations = object::locate(data, name)?;
+    Section::parse(name, data, &locations[0])
+}
+
+/// Write an already serialized `payload` into the section `name` of the
+/// binary at `path`, the same as `Enclave::write_to` would. The codec,
+/// payload version and schema recorded are taken from `like`, usually the
+/// header of the section being replaced. Up to `keep` previous payloads
+/// are kept for `rollback`.
+pub fn write_to<P: AsRef<Path>>(
+    path: P,
+    name: &str,
+    payload: &[u8],
+    checksum: Checksum,
+    like: &Header,
+    keep: usize,
+) -> Result<usize> {
+    let header = Header {
+        payload_version: like.payload_version,
+        schema: like.schema,
+        ..Header::new(payload, checksum, like.codec, 0)
+    };
+    crate::update(path.as_ref(), name, keep, Commit::Replace, |_| {
+        Ok((header, payload.to_vec()))
+    })
+}
+
+/// Previous payloads of the section `name` kept for the binary at `path`,
+/// oldest first.
+pub fn history<P: AsRef<Path>>(path: P, name: &str) -> Result<Vec<Backup>> {
+    backup::list(path.as_ref(), name)
+}
+
+/// Restore the newest previous payload of the section `name` into the
+/// binary at `path`, removing it from the history.
+pub fn rollback<P: AsRef<Path>>(path: P, name: &str) -> Result<usize> {
+    let path = path.as_ref();
+    let mut restored = None;
+    let written = crate::update(path, name, 0, Commit::Replace, |_| {
+        let backup = backup::list(path, name)?.pop().ok_or(Error::NoBackup)?;
+        let stored = (backup.header, backup.payload.clone());
+        restored = Some(backup);
+        Ok(stored)
+    })?;
+
+    if let Some(backup) = restored {
+        backup.remove()?;
+    }
+    Ok(written)
+}
+
+/// Check `pack` against the checksum recorded in `header`, giving back the
+/// payload it covers.
+pub(crate) fn verify<'a>(header: &Header, pack: &'a [u8]) -> Result<&'a [u8]> {
+    if header.algorithm == CHECKSUM_NONE {
+        return Err(Error::PayloadEmpty);
+    }
+
+    let payload = pack.get(0..header.len as usize).ok_or(Error::PayloadChecksum)?;
+    let expected = Checksum::from_id(header.algorithm)?.digest(payload);
+    if header.checksum[..expected.len()] == expected[..] {
+        Ok(payload)
+    } else {
+        Err(Error::PayloadChecksum)
+    }
+}
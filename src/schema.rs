@@ -0,0 +1,93 @@
+//! Fingerprints of a payload type's serde shape.
+//!
+//! Non self-describing codecs happily decode a payload written for an older
+//! layout of the type into garbage. The fingerprint is stored in the header
+//! so such payloads are rejected with `Error::SchemaMismatch` instead.
+
+use crate::checksum::Checksum;
+use serde::de::DeserializeOwned;
+use serde_reflection::{Tracer, TracerConfig};
+use std::convert::TryInto;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Once;
+
+/// A fingerprint computed on first use, as tracing a type is far from free.
+/// `#[enclave]` keeps one per enclave.
+pub struct Cached {
+    once: Once,
+    value: AtomicU64,
+}
+
+impl Cached {
+    pub const fn new() -> Self {
+        Self {
+            once: Once::new(),
+            value: AtomicU64::new(0),
+        }
+    }
+
+    /// Fingerprint of `T`, traced by the first call only.
+    pub fn get<T: DeserializeOwned>(&self) -> u64 {
+        self.once
+            .call_once(|| self.value.store(fingerprint::<T>(), Ordering::Relaxed));
+        self.value.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for Cached {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fingerprint of the shape `T` (de)serializes as, or `0` if it cannot be
+/// traced, such as with untagged enums. A zero fingerprint is never checked.
+pub fn fingerprint<T: DeserializeOwned>() -> u64 {
+    let mut tracer = Tracer::new(TracerConfig::default());
+    let format = match tracer.trace_simple_type::<T>() {
+        Ok((format, _)) => format,
+        Err(_) => return 0,
+    };
+    let shape = match tracer.registry() {
+        Ok(registry) => bincode::serialize(&(format, registry)),
+        Err(_) => return 0,
+    };
+
+    match shape {
+        Ok(shape) => {
+            let digest = Checksum::XxHash64.digest(&shape);
+            u64::from_le_bytes(digest[..].try_into().unwrap()).max(1)
+        }
+        Err(_) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct V0 {
+        _a: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct V1 {
+        _a: u32,
+        _b: String,
+    }
+
+    #[test]
+    fn fingerprint_follows_layout() {
+        assert_ne!(fingerprint::<V0>(), 0);
+        assert_ne!(fingerprint::<V0>(), fingerprint::<V1>());
+    }
+
+    #[test]
+    fn cached_matches_fingerprint() {
+        let cached = Cached::new();
+        assert_eq!(cached.get::<V0>(), fingerprint::<V0>());
+        assert_eq!(cached.get::<V0>(), fingerprint::<V0>());
+    }
+}
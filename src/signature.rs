@@ -0,0 +1,150 @@
+//! Detached Ed25519 signatures over enclave payloads.
+//!
+//! Signed payloads carry the 64 byte signature right after the payload,
+//! both covered by the header length and checksum. The section name is
+//! signed along with the payload, so it cannot be moved between enclaves.
+
+use crate::error::{Error, Result};
+use crate::header::Header;
+#[cfg(feature = "signing")]
+use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, Verifier};
+#[cfg(feature = "signing")]
+use std::convert::TryFrom;
+
+/// Size of the signature trailing a signed payload.
+pub const SIGNATURE_LEN: usize = 64;
+
+/// Check the signature of `payload` against `key`, giving back the payload
+/// without its signature. Enclaves with a key must be signed, those without
+/// one have any signature stripped unchecked.
+pub(crate) fn check<'a>(
+    key: Option<&[u8; 32]>,
+    section: &str,
+    header: &Header,
+    payload: &'a [u8],
+) -> Result<&'a [u8]> {
+    if !header.is_signed() {
+        return match key {
+            Some(_) => Err(Error::SignatureInvalid),
+            None => Ok(payload),
+        };
+    }
+
+    if payload.len() < SIGNATURE_LEN {
+        return Err(Error::SignatureInvalid);
+    }
+    let (body, signature) = payload.split_at(payload.len() - SIGNATURE_LEN);
+    if let Some(key) = key {
+        verify(key, section, body, signature)?;
+    }
+    Ok(body)
+}
+
+/// Sign `body` with `keypair`, giving the payload with its signature.
+#[cfg(feature = "signing")]
+pub(crate) fn sign(keypair: &Keypair, section: &str, body: &[u8]) -> Vec<u8> {
+    let signature = keypair.sign(&message(section, body));
+    let mut payload = body.to_vec();
+    payload.extend_from_slice(&signature.to_bytes());
+    payload
+}
+
+#[cfg(feature = "signing")]
+fn verify(key: &[u8; 32], section: &str, body: &[u8], signature: &[u8]) -> Result<()> {
+    let key = PublicKey::from_bytes(key).map_err(|_| Error::SignatureInvalid)?;
+    let signature = Signature::try_from(signature).map_err(|_| Error::SignatureInvalid)?;
+    key.verify(&message(section, body), &signature)
+        .map_err(|_| Error::SignatureInvalid)
+}
+
+// without the signing feature nothing can be verified, so enclaves
+// expecting a signature are always rejected.
+#[cfg(not(feature = "signing"))]
+fn verify(_key: &[u8; 32], _section: &str, _body: &[u8], _signature: &[u8]) -> Result<()> {
+    Err(Error::SignatureInvalid)
+}
+
+#[cfg(feature = "signing")]
+fn message(section: &str, body: &[u8]) -> Vec<u8> {
+    let mut message = section.as_bytes().to_vec();
+    message.push(0);
+    message.extend_from_slice(body);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Checksum;
+    use crate::header::FLAG_SIGNED;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    fn header(payload: &[u8], flags: u16) -> Header {
+        Header::new(payload, Checksum::Crc32c, 0, flags)
+    }
+
+    #[cfg(feature = "signing")]
+    fn keypair() -> Keypair {
+        let secret = ed25519_dalek::SecretKey::from_bytes(&[3; 32]).unwrap();
+        let public = PublicKey::from(&secret);
+        Keypair { secret, public }
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn signed_payload_round_trips() {
+        let keypair = keypair();
+        let payload = sign(&keypair, "appconf", b"body");
+        assert_eq!(payload.len(), 4 + SIGNATURE_LEN);
+
+        let key = keypair.public.to_bytes();
+        let header = header(&payload, FLAG_SIGNED);
+        assert_eq!(check(Some(&key), "appconf", &header, &payload).unwrap(), b"body");
+        assert_eq!(check(None, "appconf", &header, &payload).unwrap(), b"body");
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn tampered_body_is_rejected() {
+        let keypair = keypair();
+        let mut payload = sign(&keypair, "appconf", b"body");
+        payload[0] ^= 1;
+        let key = keypair.public.to_bytes();
+        let header = header(&payload, FLAG_SIGNED);
+        assert!(matches!(check(Some(&key), "appconf", &header, &payload), Err(Error::SignatureInvalid)));
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn other_section_is_rejected() {
+        let keypair = keypair();
+        let payload = sign(&keypair, "appconf", b"body");
+        let key = keypair.public.to_bytes();
+        let header = header(&payload, FLAG_SIGNED);
+        assert!(matches!(check(Some(&key), "secrets", &header, &payload), Err(Error::SignatureInvalid)));
+    }
+
+    #[cfg(feature = "signing")]
+    #[test]
+    fn other_key_is_rejected() {
+        let payload = sign(&keypair(), "appconf", b"body");
+        let header = header(&payload, FLAG_SIGNED);
+        assert!(matches!(check(Some(&KEY), "appconf", &header, &payload), Err(Error::SignatureInvalid)));
+    }
+
+    #[test]
+    fn unsigned_payload_needs_no_key() {
+        let header = header(b"body", 0);
+        assert_eq!(check(None, "appconf", &header, b"body").unwrap(), b"body");
+        assert!(matches!(check(Some(&KEY), "appconf", &header, b"body"), Err(Error::SignatureInvalid)));
+    }
+
+    #[test]
+    fn short_signed_payload_is_rejected() {
+        let payload = [0; SIGNATURE_LEN - 1];
+        let header = header(&payload, FLAG_SIGNED);
+        assert!(matches!(check(None, "appconf", &header, &payload), Err(Error::SignatureInvalid)));
+        assert!(matches!(check(Some(&KEY), "appconf", &header, &payload), Err(Error::SignatureInvalid)));
+    }
+}
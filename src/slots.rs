@@ -0,0 +1,205 @@
+//! A/B slots of an enclave.
+//!
+//! An enclave declared with `#[enclave(name, slots = 2)]` splits its
+//! section into equally sized slots, each starting with its own header.
+//! Writes go to the slot after the newest valid one with the next
+//! generation, so a torn write only damages a slot nothing relies on.
+//! Reads use the newest slot whose checksum validates.
+
+use crate::error::{Error, Result};
+use crate::header::{Header, HEADER_LEN};
+use crate::object::Location;
+use crate::raw;
+
+/// A slot of an enclave section.
+pub(crate) struct Slot<'a> {
+    pub index: usize,
+    pub count: usize,
+    pub header: Header,
+    pub pack: &'a [u8],
+    pub valid: bool,
+}
+
+/// Number of slots `section` is split into, for when the enclave's own
+/// declaration is not at hand. The first slot's header says, but if it was
+/// torn the count is found from the header of the second slot instead.
+pub(crate) fn count(section: &[u8]) -> Result<usize> {
+    if let Ok(header) = Header::parse(section) {
+        return Ok(header.slot_count());
+    }
+    (2..=usize::from(u8::MAX))
+        .find(|&count| {
+            let slot = section.get(section.len() / count..).unwrap_or_default();
+            Header::parse(slot).map_or(false, |header| header.slot_count() == count)
+        })
+        .ok_or(Error::HeaderMagic)
+}
+
+/// The slot of `section`, split into `count` slots, to read from. This is
+/// the newest slot with a valid checksum, or the first slot that parses if
+/// none are.
+pub(crate) fn active(section: &[u8], count: usize) -> Result<Slot<'_>> {
+    let count = count.max(1);
+    let size = section.len() / count;
+    let mut active: Option<Slot> = None;
+
+    for index in 0..count {
+        let bytes = &section[index * size..(index + 1) * size];
+        let header = match Header::parse(bytes) {
+            Ok(header) => header,
+            Err(_) => continue,
+        };
+        let pack = &bytes[HEADER_LEN..];
+        let valid = raw::verify(&header, pack).is_ok();
+
+        let newer = match &active {
+            None => true,
+            Some(slot) => valid && (!slot.valid || header.is_newer(&slot.header)),
+        };
+        if newer {
+            active = Some(Slot { index, count, header, pack, valid });
+        }
+    }
+
+    active.ok_or(Error::HeaderMagic)
+}
+
+/// Index and generation of the slot the next write to `section`, split
+/// into `count` slots, goes to.
+pub(crate) fn next(section: &[u8], count: usize) -> Result<(usize, usize, u16)> {
+    let slot = active(section, count)?;
+    if !slot.valid {
+        return Ok((0, slot.count, 0));
+    }
+    let index = (slot.index + 1) % slot.count;
+    Ok((index, slot.count, slot.header.generation.wrapping_add(1)))
+}
+
+/// Pack of an enclave split into `slots`, holding a copy of `header` at the
+/// start of every slot but the first, whose header precedes the pack. With
+/// every slot stamped, a torn first write still leaves headers telling the
+/// slot count.
+pub(crate) const fn stamp<const SIZE: usize>(slots: u8, header: [u8; HEADER_LEN]) -> [u8; SIZE] {
+    let mut pack = [0; SIZE];
+    let count = if slots == 0 { 1 } else { slots as usize };
+    let size = (HEADER_LEN + SIZE) / count;
+    if size < HEADER_LEN {
+        return pack;
+    }
+
+    let mut index = 1;
+    while index < count {
+        let start = index * size - HEADER_LEN;
+        let mut idx = 0;
+        while idx < HEADER_LEN {
+            pack[start + idx] = header[idx];
+            idx += 1;
+        }
+        index += 1;
+    }
+    pack
+}
+
+/// Location of slot `index` within a section at `location` split into
+/// `count` slots.
+pub(crate) fn locate(location: &Location, count: usize, index: usize) -> Location {
+    let size = location.size / count;
+    Location {
+        offset: location.offset + index * size,
+        size,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checksum::Checksum;
+
+    const SLOT: usize = HEADER_LEN + 32;
+
+    fn write_slot(section: &mut [u8], index: usize, generation: u16, payload: &[u8]) {
+        let header = Header {
+            slots: 2,
+            generation,
+            ..Header::new(payload, Checksum::Crc32c, 0, 0)
+        };
+        let slot = &mut section[index * SLOT..(index + 1) * SLOT];
+        slot[..HEADER_LEN].copy_from_slice(&header.to_bytes());
+        slot[HEADER_LEN..HEADER_LEN + payload.len()].copy_from_slice(payload);
+    }
+
+    fn section() -> Vec<u8> {
+        let mut section = vec![0; 2 * SLOT];
+        write_slot(&mut section, 0, 2, b"older");
+        write_slot(&mut section, 1, 3, b"newer");
+        section
+    }
+
+    #[test]
+    fn active_takes_newest_valid_slot() {
+        let section = section();
+        let slot = active(&section, 2).unwrap();
+        assert_eq!(slot.index, 1);
+        assert_eq!(slot.header.generation, 3);
+        assert!(slot.valid);
+    }
+
+    #[test]
+    fn active_falls_back_on_bad_checksum() {
+        let mut section = section();
+        section[SLOT + HEADER_LEN] ^= 0xff;
+        let slot = active(&section, 2).unwrap();
+        assert_eq!(slot.index, 0);
+        assert!(slot.valid);
+    }
+
+    #[test]
+    fn active_falls_back_on_torn_header() {
+        let mut section = section();
+        write_slot(&mut section, 0, 4, b"newest");
+        section[0..4].copy_from_slice(b"XXXX");
+        assert_eq!(count(&section).unwrap(), 2);
+        let slot = active(&section, 2).unwrap();
+        assert_eq!(slot.index, 1);
+        assert_eq!(&slot.pack[..5], b"newer");
+    }
+
+    #[test]
+    fn next_alternates_slots() {
+        let section = section();
+        assert_eq!(next(&section, 2).unwrap(), (0, 2, 4));
+
+        let mut torn = section.clone();
+        torn[0..4].copy_from_slice(b"XXXX");
+        assert_eq!(next(&torn, 2).unwrap(), (0, 2, 4));
+    }
+
+    #[test]
+    fn next_starts_over_without_valid_slot() {
+        let mut section = vec![0; 2 * SLOT];
+        section[..HEADER_LEN].copy_from_slice(&crate::header::empty(2, 0, 0));
+        assert_eq!(next(&section, 2).unwrap(), (0, 2, 0));
+    }
+
+    #[test]
+    fn torn_first_write_into_fresh_section() {
+        let header = crate::header::empty(2, 0, 0);
+        let mut section = header.to_vec();
+        section.extend_from_slice(&stamp::<{ 2 * SLOT - HEADER_LEN }>(2, header));
+        assert_eq!(next(&section, 2).unwrap(), (0, 2, 0));
+
+        section[0..4].copy_from_slice(b"XXXX");
+        assert_eq!(count(&section).unwrap(), 2);
+        let slot = active(&section, 2).unwrap();
+        assert_eq!(slot.index, 1);
+        assert!(!slot.valid);
+        assert_eq!(next(&section, 2).unwrap(), (0, 2, 0));
+    }
+
+    #[test]
+    fn unparseable_section_is_rejected() {
+        let section = vec![0; 2 * SLOT];
+        assert!(matches!(active(&section, 2), Err(Error::HeaderMagic)));
+        assert!(matches!(count(&section), Err(Error::HeaderMagic)));
+    }
+}
@@ -0,0 +1,117 @@
+//! Writing several enclaves in a single rewrite of the binary.
+
+use crate::error::Result;
+use crate::header::Header;
+use crate::{header_for, lock, patch, read_binary, write_binary};
+use crate::{Commit, Enclave, EnclaveCodec, EnclaveLocator};
+use serde::{de::DeserializeOwned, Serialize};
+use std::path::PathBuf;
+
+struct Staged {
+    section: &'static str,
+    keep: usize,
+    header: Header,
+    payload: Vec<u8>,
+}
+
+/// Payloads for several enclaves, committed to the binary together.
+///
+/// Each `Enclave::write` rewrites the whole binary, so writing several
+/// enclaves one after another costs a rewrite each and a failure part way
+/// leaves them inconsistent. A transaction patches all of them into one
+/// copy of the binary and replaces it once, or not at all.
+///
+/// ```no_run
+/// # use binary_enclave::{enclave, Enclave, Transaction};
+/// #[enclave(appconfig)]
+/// static CONFIG: Enclave<String, 128> = Enclave::new();
+/// #[enclave(secrets)]
+/// static SECRETS: Enclave<Vec<u8>, 128> = Enclave::new();
+///
+/// # fn main() -> binary_enclave::Result<()> {
+/// # let (conf, secrets) = (String::new(), Vec::new());
+/// Transaction::new()
+///     .stage(&CONFIG, &conf)?
+///     .stage(&SECRETS, &secrets)?
+///     .commit()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Transaction {
+    path: Option<PathBuf>,
+    staged: Vec<Staged>,
+}
+
+impl Transaction {
+    /// A transaction against the binary the staged enclaves live in, which
+    /// must be the same binary for all of them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A transaction against the binary at `path`.
+    pub fn for_binary<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: Some(path.into()),
+            staged: Vec::new(),
+        }
+    }
+
+    /// Stage `payload` to be written into `enclave`.
+    pub fn stage<T, L, const SIZE: usize>(
+        &mut self,
+        enclave: &Enclave<T, SIZE, L>,
+        payload: &T,
+    ) -> Result<&mut Self>
+    where
+        T: Default + Serialize + DeserializeOwned,
+        L: EnclaveLocator,
+    {
+        if self.path.is_none() {
+            self.path = Some(enclave.binary()?);
+        }
+
+        let payload = L::Codec::encode(payload)?;
+        self.staged.push(Staged {
+            section: L::SECTION,
+            keep: L::BACKUPS,
+            header: header_for::<L>(&payload, 0),
+            payload,
+        });
+        Ok(self)
+    }
+
+    /// Patch every staged payload into the binary and replace it once.
+    /// Nothing is written if any of them fails, and the payloads stay
+    /// staged for another attempt. Gives the total payload bytes written.
+    pub fn commit(&mut self) -> Result<usize> {
+        let path = match &self.path {
+            Some(path) => path.clone(),
+            None => return Ok(0),
+        };
+
+        let _lock = lock::FileLock::acquire(&path)?;
+        let mut data = read_binary(&path)?;
+        let mut written = 0;
+        let mut patches = Vec::new();
+        for staged in &self.staged {
+            let patched = patch(
+                &mut data,
+                staged.section,
+                staged.keep,
+                staged.header,
+                &staged.payload,
+            )?;
+            patches.push((patched, staged.keep));
+            written += staged.payload.len();
+        }
+
+        write_binary(&path, &data, &[], Commit::Replace)?;
+        self.staged.clear();
+        for (patched, keep) in patches {
+            patched.keep_backup(&path, keep)?;
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,63 @@
+//! Enclaves declared by a shared library are written into the library,
+//! not the executable that loaded it.
+#![cfg(any(target_os = "linux", target_os = "macos"))]
+
+use binary_enclave::raw;
+use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+use std::ffi::CString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Build the fixture library, returning a fresh copy of it in `dir`.
+fn build(dir: &Path) -> PathBuf {
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cdylib");
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/cdylib/Cargo.toml");
+    let status = Command::new(option_env!("CARGO").unwrap_or("cargo"))
+        .arg("build")
+        .arg("--quiet")
+        .arg("--manifest-path")
+        .arg(manifest)
+        .arg("--target-dir")
+        .arg(&target)
+        .status()
+        .expect("cargo runs");
+    assert!(status.success(), "fixture library builds");
+
+    let name = format!("{}enclave_cdylib{}", DLL_PREFIX, DLL_SUFFIX);
+    let lib = dir.join(&name);
+    fs::create_dir_all(dir).unwrap();
+    fs::copy(target.join("debug").join(&name), &lib).unwrap();
+    lib
+}
+
+unsafe fn symbol<T>(handle: *mut libc::c_void, name: &str) -> T {
+    let name = CString::new(name).unwrap();
+    let sym = libc::dlsym(handle, name.as_ptr());
+    assert!(!sym.is_null(), "{} is exported", name.to_string_lossy());
+    std::mem::transmute_copy(&sym)
+}
+
+#[test]
+fn write_targets_the_library() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cdylib-load");
+    let _ = fs::remove_dir_all(&dir);
+    let lib = build(&dir);
+    let exe_path = std::env::current_exe().unwrap();
+    let exe = fs::read(&exe_path).unwrap();
+    assert!(raw::section(&exe, "libconf").is_err());
+
+    let path = CString::new(lib.to_str().unwrap()).unwrap();
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_NOW) };
+    assert!(!handle.is_null(), "fixture library loads");
+    let write: extern "C" fn(u32) -> i32 = unsafe { symbol(handle, "libconf_write") };
+    let read: extern "C" fn() -> u32 = unsafe { symbol(handle, "libconf_read") };
+
+    assert_eq!(read(), 0);
+    assert_eq!(write(42), 0);
+
+    let section = raw::section(&fs::read(&lib).unwrap(), "libconf").unwrap();
+    let value: u32 = bincode::deserialize(section.verify().unwrap()).unwrap();
+    assert_eq!(value, 42);
+    assert_eq!(fs::read(&exe_path).unwrap(), exe);
+}
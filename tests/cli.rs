@@ -0,0 +1,69 @@
+//! Running the `enclave` tool against copies of this test binary.
+#![cfg(feature = "cli")]
+
+use binary_enclave::{enclave, Enclave};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+#[enclave(clitest)]
+static CLITEST: Enclave<String, 128> = Enclave::new();
+
+/// A fresh copy of this test binary, alone in a directory named for `test`.
+fn copy(test: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("cli").join(test);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let bin = dir.join("bin");
+    fs::copy(std::env::current_exe().unwrap(), &bin).unwrap();
+    bin
+}
+
+fn enclave(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_enclave")).args(args).output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn list_write_verify() {
+    let bin = copy("list_write_verify");
+    let path = bin.to_str().unwrap();
+    let payload = bin.with_file_name("payload");
+    fs::write(&payload, bincode::serialize("written by the tool").unwrap()).unwrap();
+
+    let listed = stdout(&enclave(&["list", path]));
+    let line = listed.lines().find(|line| line.starts_with("clitest")).unwrap();
+    assert!(line.ends_with("empty"), "{}", line);
+
+    let written = stdout(&enclave(&["write", path, "clitest", payload.to_str().unwrap()]));
+    assert!(written.starts_with("clitest: wrote"), "{}", written);
+    assert_eq!(stdout(&enclave(&["verify", path, "clitest"])), "clitest: ok\n");
+    assert_eq!(CLITEST.read_from(&bin).unwrap(), "written by the tool");
+
+    // replaced payloads are only kept when asked for
+    let backups = bin.with_file_name(".bin.clitest.backup");
+    stdout(&enclave(&["write", path, "clitest", payload.to_str().unwrap()]));
+    assert!(!backups.exists());
+    stdout(&enclave(&["write", "--keep", "2", path, "clitest", payload.to_str().unwrap()]));
+    assert_eq!(fs::read_dir(&backups).unwrap().count(), 1);
+}
+
+#[test]
+fn verify_fails_on_corruption() {
+    let bin = copy("verify_fails_on_corruption");
+    let path = bin.to_str().unwrap();
+    CLITEST.write_to(&bin, &"intact".to_string()).unwrap();
+
+    let mut data = fs::read(&bin).unwrap();
+    let payload = bincode::serialize("intact").unwrap();
+    let at = data.windows(payload.len()).position(|bytes| bytes == payload).unwrap();
+    data[at + payload.len() - 1] ^= 0xff;
+    fs::write(&bin, data).unwrap();
+
+    let verified = enclave(&["verify", path, "clitest"]);
+    assert!(!verified.status.success());
+}
@@ -0,0 +1,237 @@
+//! Writing enclaves into copies of this test binary and reading them back.
+
+use binary_enclave::{enclave, raw, Enclave, Error, Transaction};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+struct Config {
+    port: u16,
+    host: String,
+}
+
+#[enclave(testconf)]
+static CONFIG: Enclave<Config, 256> = Enclave::new();
+
+#[enclave(testhist, backups = 2)]
+static HISTORY: Enclave<Config, 256> = Enclave::new();
+
+#[enclave(testslot, slots = 2)]
+static SLOTTED: Enclave<Config, 256> = Enclave::new();
+
+#[enclave(testver, version = 2)]
+static VERSIONED: Enclave<Config, 256> = Enclave::new();
+
+#[cfg(feature = "json")]
+#[enclave(testjson, codec = "json")]
+static JSON: Enclave<Config, 256> = Enclave::new();
+
+fn config(port: u16) -> Config {
+    Config {
+        port,
+        host: format!("host-{}", port),
+    }
+}
+
+/// A fresh copy of this test binary, alone in a directory named for `test`.
+fn copy(test: &str) -> PathBuf {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("enclave").join(test);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let bin = dir.join("bin");
+    fs::copy(std::env::current_exe().unwrap(), &bin).unwrap();
+    bin
+}
+
+#[test]
+fn write_then_read() {
+    let bin = copy("write_then_read");
+    assert!(CONFIG.read_from(&bin).is_err());
+
+    for port in [80, 8080] {
+        CONFIG.write_to(&bin, &config(port)).unwrap();
+        assert_eq!(CONFIG.read_from(&bin).unwrap(), config(port));
+    }
+
+    // the replacement is staged beside the binary and renamed over it,
+    // leaving nothing behind but the lock file
+    let mut entries: Vec<_> = fs::read_dir(bin.parent().unwrap())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    entries.sort();
+    assert_eq!(entries, [".bin.lock", "bin"]);
+}
+
+#[test]
+fn oversized_payload_is_rejected() {
+    let bin = copy("oversized_payload_is_rejected");
+    let before = fs::read(&bin).unwrap();
+    let huge = Config {
+        port: 1,
+        host: "x".repeat(1024),
+    };
+    assert!(CONFIG.write_to(&bin, &huge).is_err());
+    assert_eq!(fs::read(&bin).unwrap(), before);
+}
+
+/// Run by `write_if_unchanged_conflicts` within a copy of this binary, as
+/// `write_if_unchanged` only writes the running executable.
+#[test]
+fn child_write_if_unchanged() {
+    if std::env::var_os("ENCLAVE_TEST_CHILD").is_none() {
+        return;
+    }
+    let unwritten = CONFIG.checksum();
+    CONFIG.write_if_unchanged(&unwritten, &config(1)).unwrap();
+    let conflict = CONFIG.write_if_unchanged(&unwritten, &config(2));
+    assert!(matches!(conflict, Err(Error::WriteConflict)));
+}
+
+/// Run the child half `test` of a test within the copied binary `bin`.
+fn run_child(bin: &Path, test: &str) {
+    let child = Command::new(bin)
+        .args(["--exact", test])
+        .env("ENCLAVE_TEST_CHILD", "1")
+        .output()
+        .unwrap();
+    assert!(child.status.success(), "{}", String::from_utf8_lossy(&child.stdout));
+}
+
+#[test]
+fn write_if_unchanged_conflicts() {
+    let bin = copy("write_if_unchanged_conflicts");
+    run_child(&bin, "child_write_if_unchanged");
+    assert_eq!(CONFIG.read_from(&bin).unwrap(), config(1));
+}
+
+/// Run by `decode_from_disk_sees_writes` within a copy of this binary, as
+/// `write` only writes the running executable.
+#[test]
+fn child_decode_from_disk() {
+    if std::env::var_os("ENCLAVE_TEST_CHILD").is_none() {
+        return;
+    }
+    assert_eq!(CONFIG.decode().unwrap(), config(1));
+    CONFIG.write(&config(2)).unwrap();
+    assert_eq!(CONFIG.decode().unwrap(), config(1));
+    assert_eq!(CONFIG.decode_from_disk().unwrap(), config(2));
+}
+
+#[test]
+fn decode_from_disk_sees_writes() {
+    let bin = copy("decode_from_disk_sees_writes");
+    CONFIG.write_to(&bin, &config(1)).unwrap();
+    run_child(&bin, "child_decode_from_disk");
+    assert_eq!(CONFIG.read_from(&bin).unwrap(), config(2));
+}
+
+#[test]
+fn rollback_restores_previous_payloads() {
+    let bin = copy("rollback_restores_previous_payloads");
+    for port in 1..=3 {
+        HISTORY.write_to(&bin, &config(port)).unwrap();
+    }
+
+    let ports = |bin: &Path| -> Vec<u16> {
+        let history = raw::history(bin, "testhist").unwrap();
+        history
+            .iter()
+            .map(|backup| bincode::deserialize::<Config>(&backup.payload).unwrap().port)
+            .collect()
+    };
+    assert_eq!(ports(&bin), [1, 2]);
+
+    // a failed write keeps no backup of the payload it did not replace
+    let huge = Config {
+        port: 4,
+        host: "x".repeat(1024),
+    };
+    assert!(HISTORY.write_to(&bin, &huge).is_err());
+    assert_eq!(ports(&bin), [1, 2]);
+
+    raw::rollback(&bin, "testhist").unwrap();
+    assert_eq!(HISTORY.read_from(&bin).unwrap(), config(2));
+    raw::rollback(&bin, "testhist").unwrap();
+    assert_eq!(HISTORY.read_from(&bin).unwrap(), config(1));
+    assert!(ports(&bin).is_empty());
+    assert!(matches!(raw::rollback(&bin, "testhist"), Err(Error::NoBackup)));
+    assert_eq!(HISTORY.read_from(&bin).unwrap(), config(1));
+}
+
+#[test]
+fn in_place_writes_alternate_slots() {
+    let bin = copy("in_place_writes_alternate_slots");
+    let slot = |bin: &Path| raw::section(&fs::read(bin).unwrap(), "testslot").unwrap().slot;
+
+    SLOTTED.write_in_place_to(&bin, &config(1)).unwrap();
+    let first = slot(&bin);
+    SLOTTED.write_in_place_to(&bin, &config(2)).unwrap();
+    assert_ne!(slot(&bin), first);
+    SLOTTED.write_in_place_to(&bin, &config(3)).unwrap();
+    assert_eq!(slot(&bin), first);
+    assert_eq!(SLOTTED.read_from(&bin).unwrap(), config(3));
+
+    // a torn write of the newest slot falls back to the other one
+    let newest = bincode::serialize(&config(3)).unwrap();
+    let mut data = fs::read(&bin).unwrap();
+    let torn = data.windows(newest.len()).position(|bytes| bytes == newest).unwrap();
+    data[torn] ^= 0xff;
+    fs::write(&bin, data).unwrap();
+    assert_eq!(SLOTTED.read_from(&bin).unwrap(), config(2));
+}
+
+#[test]
+fn transaction_writes_every_enclave() {
+    let bin = copy("transaction_writes_every_enclave");
+    let mut tx = Transaction::for_binary(&bin);
+    tx.stage(&CONFIG, &config(1)).unwrap().stage(&HISTORY, &config(2)).unwrap();
+    assert!(CONFIG.read_from(&bin).is_err());
+
+    tx.commit().unwrap();
+    assert_eq!(CONFIG.read_from(&bin).unwrap(), config(1));
+    assert_eq!(HISTORY.read_from(&bin).unwrap(), config(2));
+}
+
+#[test]
+fn transaction_writes_nothing_on_failure() {
+    let bin = copy("transaction_writes_nothing_on_failure");
+    let before = fs::read(&bin).unwrap();
+    let huge = Config {
+        port: 2,
+        host: "x".repeat(1024),
+    };
+
+    let mut tx = Transaction::for_binary(&bin);
+    tx.stage(&CONFIG, &config(1)).unwrap().stage(&HISTORY, &huge).unwrap();
+    assert!(tx.commit().is_err());
+    assert_eq!(fs::read(&bin).unwrap(), before);
+
+    // the payloads stay staged, failing the same way again
+    assert!(tx.commit().is_err());
+    assert_eq!(fs::read(&bin).unwrap(), before);
+}
+
+/// Write `payload` as a tool would, knowing nothing but the binary.
+fn write_raw(bin: &Path, name: &str, payload: &[u8]) {
+    let section = raw::section(&fs::read(bin).unwrap(), name).unwrap();
+    let checksum = section.checksum().unwrap_or_default();
+    raw::write_to(bin, name, payload, checksum, &section.header, 0).unwrap();
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn raw_write_keeps_the_declared_codec() {
+    let bin = copy("raw_write_keeps_the_declared_codec");
+    write_raw(&bin, "testjson", &serde_json::to_vec(&config(5)).unwrap());
+    assert_eq!(JSON.read_from(&bin).unwrap(), config(5));
+}
+
+#[test]
+fn raw_write_keeps_the_declared_version() {
+    let bin = copy("raw_write_keeps_the_declared_version");
+    write_raw(&bin, "testver", &bincode::serialize(&config(6)).unwrap());
+    assert_eq!(VERSIONED.read_from(&bin).unwrap(), config(6));
+}
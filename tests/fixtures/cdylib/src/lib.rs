@@ -0,0 +1,19 @@
+use binary_enclave::{enclave, Enclave};
+
+#[enclave(libconf)]
+pub static LIBCONF: Enclave<u32, 64> = Enclave::new();
+
+/// Write `value` into the library's enclave, 0 on success.
+#[no_mangle]
+pub extern "C" fn libconf_write(value: u32) -> i32 {
+    match LIBCONF.write(&value) {
+        Ok(_) => 0,
+        Err(_) => -1,
+    }
+}
+
+/// The value loaded from the library's enclave.
+#[no_mangle]
+pub extern "C" fn libconf_read() -> u32 {
+    LIBCONF.decode_or_default()
+}
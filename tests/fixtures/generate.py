@@ -0,0 +1,143 @@
+#!/usr/bin/env python3
+"""Generate the minimal binaries the object tests run against.
+
+Each binary carries an empty, single slot enclave section `appconf` with
+room for a 128 byte payload, beside an unrelated code section, laid out
+the way the `#[enclave]` link sections end up in real builds:
+
+* elf      `.appconf` in an ELF64 executable
+* pe.exe   `.appconf` in a PE32+ image, raw data padded to the file alignment
+* macho    `__DATA,__appconf` in a thin x86_64 Mach-O
+* fat      the same in a fat Mach-O holding x86_64 and arm64 copies
+
+They are checked in, rerun this after changing the layout.
+"""
+
+import os
+import struct
+
+HEADER_LEN = 64
+PACK = 128
+SECTION = HEADER_LEN + PACK
+CODE = b"\xc3" * 16
+
+
+def enclave():
+    header = bytearray(HEADER_LEN)
+    header[0:4] = b"ENCL"
+    header[4:6] = struct.pack("<H", 1)
+    header[20] = 1
+    return bytes(header) + bytes(PACK)
+
+
+def pad(data, align):
+    return data + bytes(-len(data) % align)
+
+
+def elf():
+    shstrtab = b"\0.text\0.appconf\0.bss\0.shstrtab\0"
+    name = shstrtab.index
+
+    body = bytearray(64)
+    text_off = len(body)
+    body += CODE
+    body = bytearray(pad(bytes(body), 16))
+    data_off = len(body)
+    body += enclave()
+    strtab_off = len(body)
+    body += shstrtab
+    body = bytearray(pad(bytes(body), 8))
+    shoff = len(body)
+
+    def shdr(name, kind, flags, offset, size, align):
+        return struct.pack("<IIQQQQIIQQ", name, kind, flags, 0, offset, size, 0, 0, align, 0)
+
+    body += shdr(0, 0, 0, 0, 0, 0)
+    body += shdr(name(b".text"), 1, 0x6, text_off, len(CODE), 16)
+    body += shdr(name(b".appconf"), 1, 0x3, data_off, SECTION, 8)
+    body += shdr(name(b".bss"), 8, 0x3, data_off + SECTION, 64, 8)
+    body += shdr(name(b".shstrtab"), 3, 0, strtab_off, len(shstrtab), 1)
+
+    ident = b"\x7fELF" + bytes([2, 1, 1, 0]) + bytes(8)
+    body[0:64] = ident + struct.pack("<HHIQQQIHHHHHH", 2, 0x3E, 1, 0, 0, shoff, 0, 64, 56, 0, 64, 5, 4)
+    return bytes(body)
+
+
+def pe():
+    file_align = 0x200
+    sections = [(b".text", CODE), (b".appconf", enclave())]
+    headers = 0x40 + 4 + 20 + 40 * len(sections)
+
+    raw = bytearray()
+    table = bytearray()
+    offset = len(pad(bytes(headers), file_align))
+    for idx, (name, data) in enumerate(sections):
+        padded = pad(data, file_align)
+        table += struct.pack(
+            "<8sIIIIIIHHI",
+            name,
+            len(data),
+            0x1000 * (idx + 1),
+            len(padded),
+            offset + len(raw),
+            0, 0, 0, 0,
+            0x60000020 if idx == 0 else 0xC0000040,
+        )
+        raw += padded
+
+    dos = bytearray(0x40)
+    dos[0:2] = b"MZ"
+    dos[0x3C:0x40] = struct.pack("<I", 0x40)
+    coff = struct.pack("<HHIIIHH", 0x8664, len(sections), 0, 0, 0, 0, 0x22)
+    image = bytes(dos) + b"PE\0\0" + coff + bytes(table)
+    return pad(image, file_align) + bytes(raw)
+
+
+def macho(cputype, cpusubtype):
+    sizeofcmds = (72 + 80) + (72 + 80 * 2)
+    code_off = 32 + sizeofcmds
+    data_off = code_off + len(CODE)
+    enclave_off = data_off + 16
+
+    def segment(name, nsects, fileoff, filesize):
+        return struct.pack(
+            "<II16sQQQQiiII", 0x19, 72 + 80 * nsects, name, 0, filesize, fileoff, filesize, 7, 3, nsects, 0
+        )
+
+    def section(name, seg, offset, size):
+        return struct.pack("<16s16sQQIIIIIIII", name, seg, 0, size, offset, 3, 0, 0, 0, 0, 0, 0)
+
+    cmds = segment(b"__TEXT", 1, code_off, len(CODE))
+    cmds += section(b"__text", b"__TEXT", code_off, len(CODE))
+    cmds += segment(b"__DATA", 2, data_off, 16 + SECTION)
+    cmds += section(b"__data", b"__DATA", data_off, 16)
+    cmds += section(b"__appconf", b"__DATA", enclave_off, SECTION)
+
+    header = struct.pack("<IiiIIIII", 0xFEEDFACF, cputype, cpusubtype, 2, 2, len(cmds), 0, 0)
+    return header + cmds + CODE + bytes(16) + enclave()
+
+
+def fat():
+    arches = [(0x01000007, 3), (0x0100000C, 0)]
+    align = 12
+    out = bytearray(struct.pack(">II", 0xCAFEBABE, len(arches)))
+    offset = 1 << align
+    table, images = bytearray(), bytearray()
+    for cputype, cpusubtype in arches:
+        image = macho(cputype, cpusubtype)
+        table += struct.pack(">iiIII", cputype, cpusubtype, offset + len(images), len(image), align)
+        images += pad(image, 1 << align)
+    out += table
+    return pad(bytes(out), 1 << align) + bytes(images)
+
+
+def main():
+    here = os.path.dirname(os.path.abspath(__file__))
+    fixtures = {"elf": elf(), "pe.exe": pe(), "macho": macho(0x01000007, 3), "fat": fat()}
+    for name, data in fixtures.items():
+        with open(os.path.join(here, name), "wb") as out:
+            out.write(data)
+
+
+if __name__ == "__main__":
+    main()